@@ -1,18 +1,96 @@
+use std::collections::HashMap;
 use std::fs;
+use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
+use color_eyre::eyre::eyre;
 use color_eyre::Result;
-use crossterm::event::{KeyCode, KeyEvent};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 
-use crate::file_browser::BrowserState;
-use crate::selection::SelectionState;
+use crate::command::Command;
+use crate::config::Config;
+use crate::file_browser::{BrowserState, FileEntry};
+use crate::log::verbose;
+use crate::macros::{MacroStep, Macros};
+use crate::selection::{
+    collapse_home, format_invalid_path, resolve_invalid, OutputOptions, SelectionState,
+};
+use crate::ui::{DirCount, Glyphs, SelectionStyle};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How long a first Enter stays armed with `--require-double-confirm`
+const CONFIRM_WINDOW: Duration = Duration::from_secs(3);
+
+/// Type-ahead keys pressed within this of each other form one prefix
+const TYPE_AHEAD_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Additions larger than this are spread over several event loop ticks so
+/// the status bar can report progress
+const ADD_CHUNK: usize = 500;
+
+/// Status shown when `--read-only` refuses a key or command
+const READ_ONLY_MESSAGE: &str = "Read-only: the selection can't be changed";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AppAction {
     Continue,
     Quit,
     Confirm,
     Save,
+    /// Put this text on the terminal's clipboard
+    Copy(String),
+}
+
+/// What Enter does on a selected directory in the Selected pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DirEnter {
+    /// Open the directory in the Files pane
+    #[default]
+    Navigate,
+    /// Open it in the Files pane, listing only its selected contents
+    Expand,
+    /// Confirm the whole selection, like Enter anywhere else
+    Confirm,
+}
+
+/// What Space does on an invalid entry in the Files pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum InvalidSpace {
+    /// Select or deselect it like any other entry
+    #[default]
+    Toggle,
+    /// Drop it from the selection and stop listing it
+    Remove,
+    /// Nothing; invalid entries are read-only reminders
+    Ignore,
+}
+
+/// Which selections the Selected pane lists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectedFilter {
+    #[default]
+    All,
+    Valid,
+    Invalid,
+}
+
+impl SelectedFilter {
+    pub fn next(self) -> Self {
+        match self {
+            Self::All => Self::Valid,
+            Self::Valid => Self::Invalid,
+            Self::Invalid => Self::All,
+        }
+    }
+
+    pub fn shows(self, is_valid: bool) -> bool {
+        match self {
+            Self::All => true,
+            Self::Valid => is_valid,
+            Self::Invalid => !is_valid,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -22,6 +100,26 @@ pub enum FocusedPane {
     Selected,
 }
 
+/// Which text prompt, if any, is capturing key presses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputMode {
+    #[default]
+    Normal,
+    Search,
+    Command,
+    /// Editing the note of a path in the Selected pane
+    Annotate,
+    /// Typing an extension whose files get selected
+    Extension,
+}
+
+/// A large addition being applied a chunk at a time
+#[derive(Debug)]
+struct PendingAdd {
+    paths: std::vec::IntoIter<PathBuf>,
+    total: usize,
+}
+
 impl FocusedPane {
     fn toggle(self) -> Self {
         match self {
@@ -38,42 +136,223 @@ pub struct App {
     pub base_dir: PathBuf,
     pub focused_pane: FocusedPane,
     pub selected_cursor: usize,
+    /// Valid/invalid filter on the Selected pane, cycled with `v`
+    pub selected_filter: SelectedFilter,
     pub selected_scroll_offset: usize,
-    pub search_mode: bool,
+    pub input_mode: InputMode,
     pub search_query: String,
-    use_absolute: bool,
+    /// Whether the search query matches no entry in the current directory
+    pub search_failed: bool,
+    pub command_input: String,
+    pub note_input: String,
+    pub extension_input: String,
+    /// Match extensions typed at the `x` prompt regardless of case
+    pub extension_ignore_case: bool,
+    /// One-shot feedback shown in the status bar until the next key press
+    pub status_message: Option<String>,
+    /// Selections awaiting a y/n answer before being removed
+    pub pending_removal: Option<Vec<PathBuf>>,
+    pub selection_style: SelectionStyle,
+    pub dir_count: DirCount,
+    pub glyphs: Glyphs,
+    /// Sizes where files turn yellow and red, when coloring by size
+    pub size_thresholds: Option<(u64, u64)>,
+    /// Highlight files on disk that aren't selected, for checking a list
+    /// against the tree
+    pub diff: bool,
+    /// Show file-type icons before entry names
+    pub icons: bool,
+    /// Icons replacing the built-in ones, by lowercase extension
+    pub icon_overrides: HashMap<String, String>,
+    /// Group the Selected pane under per-directory headers
+    pub group_selected: bool,
+    /// Show the directory shared by all selections once, above the list
+    pub collapse_prefix: bool,
+    /// Show how long ago each entry was modified in the Files pane
+    pub show_mtime: bool,
+    /// Show permissions and ownership of each entry in the Files pane
+    pub show_permissions: bool,
+    /// List only selected entries, and directories leading to them
+    pub only_selected: bool,
+    /// List selected entries first in the Files pane
+    selected_first: bool,
+    /// Listing and selection revisions, with `only_selected` and
+    /// `selected_first`, as of the last `arrange_listing`
+    arranged: Option<(u64, u64, bool, bool)>,
+    /// Show only the focused pane, at full width
+    pub single_pane: bool,
+    /// Draw scrollbars on lists longer than their pane
+    pub scrollbar: bool,
+    /// Draw in color; without it backgrounds become reverse video
+    pub color: bool,
+    /// Draw the status bar even when it has no prompt or message
+    show_status: bool,
+    /// Draw the key legend
+    pub show_legend: bool,
+    /// Picker mode: at most one path is selected, and Enter on a file picks it
+    pub single: bool,
+    /// Replace both panes with a list of the invalid selections
+    pub reviewing_invalid: bool,
+    pub invalid_cursor: usize,
+    /// Show the full absolute current directory in the status bar
+    pub status_absolute: bool,
+    confirm_threshold: usize,
+    /// Cancel automatically after this long without a key press
+    timeout: Option<Duration>,
+    require_double_confirm: bool,
+    /// Fewest selections Enter confirms (`--min-select`)
+    min_select: usize,
+    /// Space on a directory toggles its contents like `r`
+    space_recurses: bool,
+    dir_enter: DirEnter,
+    invalid_space: InvalidSpace,
+    /// Unbound printable keys jump to entries starting with them
+    type_ahead: bool,
+    /// Browsing only: keys and commands that change the selection are refused
+    pub read_only: bool,
+    type_ahead_prefix: String,
+    last_type_ahead: Instant,
+    /// When the first of two confirming Enters was pressed
+    confirm_armed: Option<Instant>,
+    last_input: Instant,
+    output: OutputOptions,
     selections_file: Option<PathBuf>,
+    pending_add: Option<PendingAdd>,
+    /// Selected path whose note is being edited
+    note_target: Option<PathBuf>,
+    pub macros: Macros,
 }
 
 impl App {
     pub fn new(
         start_dir: PathBuf,
-        show_hidden: bool,
-        use_absolute: bool,
-        pre_selected: Vec<PathBuf>,
-        selections_file: Option<PathBuf>,
+        mut pre_selected: Vec<PathBuf>,
+        config: &Config,
     ) -> Result<Self> {
         let base_dir = start_dir.canonicalize()?;
-        let mut browser = BrowserState::new(start_dir, show_hidden)?;
+        let browse_dir = browse_dir(&base_dir, config)?;
+        let mut browser = BrowserState::new(
+            base_dir.clone(),
+            browse_dir,
+            config.show_hidden,
+            config.sort_options(),
+            config.hidden_patterns.clone(),
+            config.page_size.map(NonZeroUsize::get),
+        )?;
+        if let Some(name) = config.near.as_ref().and_then(|near| near.file_name()) {
+            let near = browser.current_dir.join(name);
+            browser.move_to(&near);
+        }
+        if config.single {
+            pre_selected.truncate(1);
+        }
         let mut selection = SelectionState::new();
-        selection.add_paths(pre_selected);
+        selection.set_limit(config.max_select);
+        let provided = pre_selected.len();
+        let counts = selection.add_paths(pre_selected);
+        selection.take_limit_hit();
+        let invalid = selection.invalid_count();
+        verbose!(
+            1,
+            "pre-selection: {} paths, {} duplicates collapsed, {} over the limit, {} invalid",
+            provided,
+            counts.duplicates,
+            counts.refused,
+            invalid
+        );
+        // Explains a Selected count lower than the number of paths passed in
+        let mut reasons = Vec::new();
+        if counts.duplicates > 0 || invalid > 0 {
+            reasons.push(format!(
+                "{} duplicate and {} invalid pre-selections",
+                counts.duplicates, invalid
+            ));
+        }
+        if let (true, Some(limit)) = (counts.refused > 0, config.max_select) {
+            reasons.push(format!(
+                "Limit reached ({}), {} pre-selections dropped",
+                limit, counts.refused
+            ));
+        }
+        let status_message = (!reasons.is_empty()).then(|| reasons.join("; "));
 
         let invalid_paths: Vec<PathBuf> = selection.iter_invalid().cloned().collect();
         browser.add_invalid_paths(invalid_paths);
+        // Keep the file being edited from being selected into itself
+        let selections_file = config.selections_file.as_deref();
+        if let Some(Ok(file)) = selections_file.map(canonical_location) {
+            if !config.show_selections_file {
+                browser.exclude(file);
+            }
+        }
         browser.refresh()?;
 
-        Ok(Self {
+        let mut app = Self {
             browser,
             selection,
-            use_absolute,
+            output: config.output_options(),
             base_dir,
             focused_pane: FocusedPane::default(),
             selected_cursor: 0,
+            selected_filter: SelectedFilter::All,
             selected_scroll_offset: 0,
-            search_mode: false,
+            input_mode: InputMode::default(),
             search_query: String::new(),
-            selections_file,
-        })
+            search_failed: false,
+            command_input: String::new(),
+            note_input: String::new(),
+            extension_input: String::new(),
+            extension_ignore_case: true,
+            status_message,
+            pending_removal: None,
+            selection_style: config.selection_style,
+            dir_count: config.dir_count,
+            glyphs: config.glyphs(),
+            size_thresholds: config.size_colors.then_some(config.size_thresholds),
+            diff: config.diff,
+            icons: config.icons,
+            icon_overrides: config.icon_overrides.iter().cloned().collect(),
+            group_selected: false,
+            collapse_prefix: false,
+            show_mtime: config.show_mtime,
+            show_permissions: config.show_permissions,
+            only_selected: false,
+            selected_first: config.selected_first,
+            arranged: None,
+            single_pane: config.single_pane,
+            scrollbar: !config.no_scrollbar,
+            color: config.color(),
+            show_status: !config.no_status,
+            show_legend: !config.no_legend,
+            single: config.single,
+            reviewing_invalid: false,
+            invalid_cursor: 0,
+            status_absolute: config.absolute_status,
+            confirm_threshold: config.confirm_threshold,
+            timeout: config.timeout.map(Duration::from_secs),
+            require_double_confirm: config.require_double_confirm,
+            min_select: config.min_select,
+            space_recurses: config.space_recurses,
+            dir_enter: config.selected_dir_enter,
+            invalid_space: config.invalid_space,
+            type_ahead: config.type_ahead,
+            read_only: config.read_only,
+            type_ahead_prefix: String::new(),
+            last_type_ahead: Instant::now(),
+            confirm_armed: None,
+            last_input: Instant::now(),
+            selections_file: config.selections_file.clone(),
+            pending_add: None,
+            note_target: None,
+            macros: Macros::default(),
+        };
+        app.arrange_listing()?;
+        if let Some(ref query) = config.search {
+            app.input_mode = InputMode::Search;
+            app.search_query = query.clone();
+            app.jump_to_match();
+        }
+        Ok(app)
     }
 
     pub fn can_save(&self) -> bool {
@@ -84,14 +363,219 @@ impl App {
         self.selections_file.as_ref()
     }
 
+    /// Time left before the idle timeout cancels, if one is configured
+    pub fn timeout_remaining(&self) -> Option<Duration> {
+        self.timeout
+            .map(|timeout| timeout.saturating_sub(self.last_input.elapsed()))
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.timeout_remaining().is_some_and(|left| left.is_zero())
+    }
+
+    /// Paths added so far and in total while a large addition is running
+    pub fn add_progress(&self) -> Option<(usize, usize)> {
+        self.pending_add
+            .as_ref()
+            .map(|pending| (pending.total - pending.paths.len(), pending.total))
+    }
+
+    /// Apply the next chunk of a pending addition. Called once per event
+    /// loop iteration so the UI redraws between chunks.
+    pub fn process_pending_add(&mut self) {
+        let Some(ref mut pending) = self.pending_add else {
+            return;
+        };
+        self.selection
+            .add_paths(pending.paths.by_ref().take(ADD_CHUNK));
+        if pending.paths.len() == 0 {
+            self.pending_add = None;
+            self.report_limit();
+        }
+    }
+
+    /// Apply whatever is left of a pending addition at once, so that keys
+    /// and output always see the complete selection.
+    fn finish_pending_add(&mut self) {
+        if let Some(pending) = self.pending_add.take() {
+            self.selection.add_paths(pending.paths);
+        }
+    }
+
+    /// Add paths to the selection, deferring large batches to
+    /// `process_pending_add`. In single mode one path replaces the
+    /// selection and more are refused.
+    fn add_selection(&mut self, paths: Vec<PathBuf>) {
+        self.finish_pending_add();
+        if self.single {
+            if paths.len() > 1 {
+                self.status_message = Some("Only one path can be selected (--single)".to_owned());
+            } else {
+                self.selection.clear();
+                self.selection.add_paths(paths);
+            }
+        } else if paths.len() <= ADD_CHUNK {
+            self.selection.add_paths(paths);
+        } else {
+            self.pending_add = Some(PendingAdd {
+                total: paths.len(),
+                paths: paths.into_iter(),
+            });
+        }
+    }
+
+    /// Whether a first Enter is waiting for the second one
+    pub fn confirm_armed(&self) -> bool {
+        self.confirm_armed
+            .is_some_and(|armed| armed.elapsed() < CONFIRM_WINDOW)
+    }
+
+    /// Whether the status bar takes a row. When hidden it still appears
+    /// for prompts and messages, which would otherwise go unseen.
+    pub fn status_shown(&self) -> bool {
+        self.show_status
+            || self.input_mode != InputMode::Normal
+            || self.pending_removal.is_some()
+            || self.status_message.is_some()
+            || self.add_progress().is_some()
+            || self.confirm_armed()
+    }
+
+    /// Cycle the bars: both shown, legend hidden, both hidden
+    fn cycle_bars(&mut self) {
+        (self.show_status, self.show_legend) = match (self.show_status, self.show_legend) {
+            (true, true) => (true, false),
+            (true, false) => (false, false),
+            _ => (true, true),
+        };
+    }
+
     pub fn handle_key(&mut self, key: KeyEvent) -> Result<AppAction> {
-        if self.search_mode {
-            return self.handle_search_key(key);
+        self.status_message = None;
+        self.last_input = Instant::now();
+        // Any key other than a second Enter disarms confirmation
+        if key.code != KeyCode::Enter || self.input_mode != InputMode::Normal {
+            self.confirm_armed = None;
+        }
+        self.finish_pending_add();
+
+        // Raw mode delivers Ctrl-C as a key; it cancels from any mode
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(AppAction::Quit);
+        }
+
+        let normal_mode = self.input_mode == InputMode::Normal
+            && !self.reviewing_invalid
+            && self.pending_removal.is_none();
+        match self.macros.intercept(key, normal_mode) {
+            MacroStep::Consumed => return Ok(AppAction::Continue),
+            MacroStep::Replay(keys) => return self.replay_macro(keys),
+            MacroStep::Pass => self.macros.record(key),
+        }
+
+        if let Some(paths) = self.pending_removal.take() {
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                self.selection.remove_paths(&paths);
+                self.status_message = Some(format!("Deselected {} files", paths.len()));
+                self.arrange_listing()?;
+            } else {
+                self.status_message = Some("Deselect cancelled".to_owned());
+            }
+            return Ok(AppAction::Continue);
+        }
+
+        if self.reviewing_invalid {
+            self.handle_review_key(key);
+            return Ok(AppAction::Continue);
+        }
+
+        let action = match self.input_mode {
+            InputMode::Normal => self.handle_normal_key(key)?,
+            InputMode::Search => return self.handle_search_key(key),
+            InputMode::Command => self.handle_command_key(key)?,
+            InputMode::Annotate => return self.handle_note_key(key),
+            InputMode::Extension => self.handle_extension_key(key),
+        };
+        // Selections and the directory may both have changed
+        self.arrange_listing()?;
+        self.report_vanished_dir();
+        self.report_limit();
+        Ok(action)
+    }
+
+    /// Between key presses, notice the current directory being removed and
+    /// move up out of it
+    pub fn check_current_dir(&mut self) -> Result<()> {
+        if !self.browser.current_dir.is_dir() {
+            self.browser.refresh()?;
+            self.arrange_listing()?;
+            self.report_vanished_dir();
+        }
+        Ok(())
+    }
+
+    fn report_limit(&mut self) {
+        if let (true, Some(limit)) = (self.selection.take_limit_hit(), self.selection.limit()) {
+            self.status_message = Some(format!("Limit reached ({})", limit));
+        }
+    }
+
+    fn report_vanished_dir(&mut self) {
+        if let Some(dir) = self.browser.take_vanished() {
+            self.status_message = Some(format!(
+                "{} was removed, moved to {}",
+                display_relative(&dir, &self.base_dir),
+                display_relative(&self.browser.current_dir, &self.base_dir)
+            ));
+        }
+    }
+
+    /// Feed recorded keys back through `handle_key`, stopping early if one
+    /// of them quits, confirms or saves
+    fn replay_macro(&mut self, keys: Vec<KeyEvent>) -> Result<AppAction> {
+        self.macros.set_replaying(true);
+        let mut result = Ok(AppAction::Continue);
+        for key in keys {
+            result = self.handle_key(key);
+            if !matches!(result, Ok(AppAction::Continue)) {
+                break;
+            }
         }
+        self.macros.set_replaying(false);
+        result
+    }
 
+    fn handle_normal_key(&mut self, key: KeyEvent) -> Result<AppAction> {
+        if self.read_only && is_mutating_key(key.code) {
+            self.status_message = Some(READ_ONLY_MESSAGE.to_owned());
+            return Ok(AppAction::Continue);
+        }
         match key.code {
             KeyCode::Char('q') | KeyCode::Esc => Ok(AppAction::Quit),
-            KeyCode::Enter => Ok(AppAction::Confirm),
+            KeyCode::Enter => {
+                if self.single && self.focused_pane == FocusedPane::Files {
+                    // The picker navigates on directories and confirms only
+                    // with a file picked
+                    match self.browser.current_entry() {
+                        Some(entry) if entry.is_dir => {
+                            let _ = self.browser.enter_directory();
+                            return Ok(AppAction::Continue);
+                        }
+                        None if !self.read_only => return Ok(AppAction::Continue),
+                        _ => {}
+                    }
+                    if !self.read_only {
+                        self.pick_current_file();
+                    }
+                }
+                if self.dir_enter != DirEnter::Confirm {
+                    if let Some(dir) = self.selected_dir_at_cursor() {
+                        self.open_selected_dir(&dir)?;
+                        return Ok(AppAction::Continue);
+                    }
+                }
+                Ok(self.confirm())
+            }
             KeyCode::Tab => {
                 self.focused_pane = self.focused_pane.toggle();
                 self.clamp_selected_cursor();
@@ -117,6 +601,33 @@ impl App {
                 }
                 Ok(AppAction::Continue)
             }
+            KeyCode::Backspace | KeyCode::Char('[') => {
+                if self.focused_pane == FocusedPane::Files {
+                    let _ = self.browser.go_back();
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char(']') => {
+                if self.focused_pane == FocusedPane::Files {
+                    let _ = self.browser.go_forward();
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('-') => {
+                if self.focused_pane == FocusedPane::Files {
+                    let _ = self.browser.go_previous();
+                }
+                Ok(AppAction::Continue)
+            }
+            // Shift-Space only arrives from terminals that report modifiers on it
+            KeyCode::Insert => {
+                self.toggle_and_advance();
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char(' ') if key.modifiers.contains(KeyModifiers::SHIFT) => {
+                self.toggle_and_advance();
+                Ok(AppAction::Continue)
+            }
             KeyCode::Char(' ') => {
                 self.handle_space();
                 Ok(AppAction::Continue)
@@ -127,16 +638,45 @@ impl App {
                 }
                 Ok(AppAction::Continue)
             }
+            KeyCode::Char('L') => {
+                if self.focused_pane == FocusedPane::Files {
+                    let loaded = self.browser.load_more()?;
+                    if loaded > 0 {
+                        self.status_message = Some(format!("Loaded {} more entries", loaded));
+                    }
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('R') => {
+                if self.focused_pane == FocusedPane::Files {
+                    let dir = &self.browser.current_dir;
+                    let removed = self.selection.remove_below(dir, &self.base_dir);
+                    self.status_message = Some(format!(
+                        "Deselected {} under {}",
+                        removed,
+                        display_relative(dir, &self.base_dir)
+                    ));
+                }
+                Ok(AppAction::Continue)
+            }
             KeyCode::Char('a') => {
                 if self.focused_pane == FocusedPane::Files {
                     self.toggle_all_in_current();
                 }
                 Ok(AppAction::Continue)
             }
+            KeyCode::Char(',') => {
+                self.select_current_dir();
+                Ok(AppAction::Continue)
+            }
             KeyCode::Char('.') => {
                 self.browser.toggle_hidden()?;
                 Ok(AppAction::Continue)
             }
+            KeyCode::Char('H') => {
+                self.browser.toggle_hidden_only()?;
+                Ok(AppAction::Continue)
+            }
             KeyCode::Char('s') => {
                 if self.can_save() {
                     Ok(AppAction::Save)
@@ -146,11 +686,101 @@ impl App {
             }
             KeyCode::Char('/') => {
                 if self.focused_pane == FocusedPane::Files {
-                    self.search_mode = true;
+                    self.input_mode = InputMode::Search;
                     self.search_query.clear();
+                    self.search_failed = false;
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('p') => {
+                self.status_absolute = !self.status_absolute;
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('O') => {
+                self.open_in_file_manager();
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('g') => {
+                self.group_selected = !self.group_selected;
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('c') => {
+                self.collapse_prefix = !self.collapse_prefix;
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('m') => {
+                self.show_mtime = !self.show_mtime;
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('x') => {
+                if self.focused_pane == FocusedPane::Files {
+                    self.input_mode = InputMode::Extension;
+                    self.extension_input.clear();
                 }
                 Ok(AppAction::Continue)
             }
+            KeyCode::Char('S') => {
+                if self.focused_pane == FocusedPane::Files {
+                    self.select_same_stem();
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('o') => {
+                self.show_permissions = !self.show_permissions;
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('i') => {
+                self.reviewing_invalid = true;
+                self.invalid_cursor = 0;
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('f') => {
+                self.only_selected = !self.only_selected;
+                if !self.only_selected {
+                    self.browser.refresh()?;
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('F') => {
+                self.selected_first = !self.selected_first;
+                if !self.selected_first {
+                    self.browser.refresh()?;
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('Y') => match self.path_at_cursor() {
+                Some(path) => {
+                    self.status_message = Some(format!("Copied {}", path));
+                    Ok(AppAction::Copy(path))
+                }
+                None => Ok(AppAction::Continue),
+            },
+            KeyCode::F(1) => {
+                self.cycle_bars();
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char(':') => {
+                self.input_mode = InputMode::Command;
+                self.command_input.clear();
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('t') => {
+                if self.focused_pane == FocusedPane::Selected {
+                    self.start_note();
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char('v') => {
+                if self.focused_pane == FocusedPane::Selected {
+                    self.selected_filter = self.selected_filter.next();
+                    self.clamp_selected_cursor();
+                }
+                Ok(AppAction::Continue)
+            }
+            KeyCode::Char(c) if self.type_ahead && self.focused_pane == FocusedPane::Files => {
+                self.type_ahead(c);
+                Ok(AppAction::Continue)
+            }
             _ => Ok(AppAction::Continue),
         }
     }
@@ -158,11 +788,11 @@ impl App {
     fn handle_search_key(&mut self, key: KeyEvent) -> Result<AppAction> {
         match key.code {
             KeyCode::Esc => {
-                self.search_mode = false;
+                self.input_mode = InputMode::Normal;
                 self.search_query.clear();
             }
             KeyCode::Enter => {
-                self.search_mode = false;
+                self.input_mode = InputMode::Normal;
                 // Keep cursor on current match, don't clear query for visual feedback
             }
             KeyCode::Backspace => {
@@ -178,7 +808,519 @@ impl App {
         Ok(AppAction::Continue)
     }
 
+    fn handle_command_key(&mut self, key: KeyEvent) -> Result<AppAction> {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.command_input.clear();
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let input = std::mem::take(&mut self.command_input);
+                match Command::parse(&input) {
+                    Ok(command) => return self.run_command(command),
+                    Err(message) => self.status_message = Some(message),
+                }
+            }
+            KeyCode::Backspace => {
+                // Backspace on an empty prompt leaves command mode, like vim
+                let was_empty = self.command_input.pop().is_none();
+                if was_empty {
+                    self.input_mode = InputMode::Normal;
+                }
+            }
+            KeyCode::Char(c) => {
+                self.command_input.push(c);
+            }
+            _ => {}
+        }
+        Ok(AppAction::Continue)
+    }
+
+    /// The focused pane's entry under the cursor, shown as it would be
+    /// output: absolute with `--absolute`, else like the Selected pane
+    fn path_at_cursor(&self) -> Option<String> {
+        let (path, is_valid) = match self.focused_pane {
+            FocusedPane::Files => {
+                let entry = self.browser.current_entry()?;
+                (entry.path.clone(), !entry.is_invalid)
+            }
+            FocusedPane::Selected => self.get_selected_list().get(self.selected_cursor)?.clone(),
+        };
+        Some(match (self.output.absolute, is_valid) {
+            (false, _) => self.format_path_for_display(&path, is_valid),
+            (true, true) => self.tilde(path.display().to_string()),
+            (true, false) => {
+                self.tilde(resolve_invalid(&path, &self.base_dir).display().to_string())
+            }
+        })
+    }
+
+    /// The selected directory under the Selected pane's cursor, if any
+    fn selected_dir_at_cursor(&self) -> Option<PathBuf> {
+        if self.focused_pane != FocusedPane::Selected {
+            return None;
+        }
+        let items = self.get_selected_list();
+        let (path, is_valid) = items.get(self.selected_cursor)?;
+        (*is_valid && path.is_dir()).then(|| path.clone())
+    }
+
+    /// Enter-on-directory in the Selected pane, per `--selected-dir-enter`
+    fn open_selected_dir(&mut self, dir: &Path) -> Result<()> {
+        self.browser.goto(dir)?;
+        self.focused_pane = FocusedPane::Files;
+        if self.dir_enter == DirEnter::Expand {
+            self.only_selected = true;
+        }
+        Ok(())
+    }
+
+    /// Open the current directory, or in the Selected pane the directory of
+    /// the highlighted selection, in the system file manager. The launcher
+    /// runs detached, so the TUI stays up.
+    fn open_in_file_manager(&mut self) {
+        let dir = match self.focused_pane {
+            FocusedPane::Files => self.browser.current_dir.clone(),
+            FocusedPane::Selected => {
+                let items = self.get_selected_list();
+                let Some((path, _)) = items.get(self.selected_cursor) else {
+                    return;
+                };
+                if path.is_dir() {
+                    path.clone()
+                } else {
+                    path.parent()
+                        .map(Path::to_path_buf)
+                        .unwrap_or_else(|| path.clone())
+                }
+            }
+        };
+
+        self.status_message = Some(match open::that_detached(&dir) {
+            Ok(()) => format!("Opened {}", dir.display()),
+            Err(e) => format!("Cannot open {}: {}", dir.display(), e),
+        });
+    }
+
+    fn confirm(&mut self) -> AppAction {
+        let have = self.selection.count();
+        if have < self.min_select {
+            self.confirm_armed = None;
+            self.status_message =
+                Some(format!("Need at least {} (have {})", self.min_select, have));
+            return AppAction::Continue;
+        }
+        if !self.require_double_confirm || self.confirm_armed() {
+            self.confirm_armed = None;
+            return AppAction::Confirm;
+        }
+        self.confirm_armed = Some(Instant::now());
+        AppAction::Continue
+    }
+
+    fn handle_review_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('i') | KeyCode::Char('q') | KeyCode::Esc => {
+                self.reviewing_invalid = false;
+            }
+            KeyCode::Char('k') | KeyCode::Up => {
+                self.invalid_cursor = self.invalid_cursor.saturating_sub(1);
+            }
+            KeyCode::Char('j') | KeyCode::Down
+                if self.invalid_cursor + 1 < self.selection.invalid_count() =>
+            {
+                self.invalid_cursor += 1;
+            }
+            KeyCode::Char(' ') if self.read_only => {
+                self.status_message = Some(READ_ONLY_MESSAGE.to_owned());
+            }
+            KeyCode::Char(' ') => {
+                let items = self.get_invalid_list();
+                if let Some(path) = items.get(self.invalid_cursor) {
+                    self.selection.remove_invalid(path);
+                }
+                let count = self.selection.invalid_count();
+                self.invalid_cursor = self.invalid_cursor.min(count.saturating_sub(1));
+                self.clamp_selected_cursor();
+            }
+            _ => {}
+        }
+    }
+
+    /// Invalid selections in display order, for the review view
+    pub fn get_invalid_list(&self) -> Vec<PathBuf> {
+        let mut items: Vec<PathBuf> = self.selection.iter_invalid().cloned().collect();
+        items.sort_by_cached_key(|path| self.format_path_for_display(path, false));
+        items
+    }
+
+    /// Apply `only_selected` and `selected_first` to the listing, unless
+    /// neither it, the selection nor those settings changed since last time
+    fn arrange_listing(&mut self) -> Result<()> {
+        let state = |app: &Self| {
+            (
+                app.browser.revision(),
+                app.selection.revision(),
+                app.only_selected,
+                app.selected_first,
+            )
+        };
+        if self.arranged == Some(state(self)) {
+            return Ok(());
+        }
+        self.filter_files()?;
+        self.float_selected();
+        self.arranged = Some(state(self));
+        Ok(())
+    }
+
+    /// With `only_selected`, re-list the current directory keeping only
+    /// selected entries and directories with selections inside
+    fn filter_files(&mut self) -> Result<()> {
+        if !self.only_selected {
+            return Ok(());
+        }
+        self.browser.refresh()?;
+        let selection = &self.selection;
+        let base_dir = &self.base_dir;
+        self.browser.retain_entries(|entry| {
+            if entry.is_invalid {
+                selection.is_invalid_selected(&entry.path)
+            } else {
+                selection.is_selected(&entry.path)
+                    || (entry.is_dir && selection.any_selected_below(&entry.path, base_dir))
+            }
+        });
+        Ok(())
+    }
+
+    /// With `selected_first`, list selected entries before the rest, each
+    /// group keeping the listing order
+    fn float_selected(&mut self) {
+        if !self.selected_first {
+            return;
+        }
+        let selection = &self.selection;
+        self.browser.float_to_top(|entry| {
+            if entry.is_invalid {
+                selection.is_invalid_selected(&entry.path)
+            } else {
+                selection.is_selected(&entry.path)
+            }
+        });
+    }
+
+    fn start_note(&mut self) {
+        let items = self.get_selected_list();
+        let Some((path, _)) = items.get(self.selected_cursor) else {
+            return;
+        };
+        self.note_input = self.selection.note(path).unwrap_or_default().to_owned();
+        self.note_target = Some(path.clone());
+        self.input_mode = InputMode::Annotate;
+    }
+
+    fn handle_note_key(&mut self, key: KeyEvent) -> Result<AppAction> {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.note_target = None;
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                if let Some(path) = self.note_target.take() {
+                    let note = std::mem::take(&mut self.note_input);
+                    self.selection.set_note(&path, note.trim().to_owned());
+                }
+            }
+            KeyCode::Backspace => {
+                self.note_input.pop();
+            }
+            KeyCode::Char(c) => self.note_input.push(c),
+            _ => {}
+        }
+        Ok(AppAction::Continue)
+    }
+
+    fn handle_extension_key(&mut self, key: KeyEvent) -> AppAction {
+        match key.code {
+            KeyCode::Esc => {
+                self.input_mode = InputMode::Normal;
+                self.extension_input.clear();
+            }
+            KeyCode::Enter => {
+                self.input_mode = InputMode::Normal;
+                let extension = std::mem::take(&mut self.extension_input);
+                self.select_extension(extension.trim().trim_start_matches('.'));
+            }
+            KeyCode::Backspace => {
+                self.extension_input.pop();
+            }
+            KeyCode::Tab => self.extension_ignore_case = !self.extension_ignore_case,
+            KeyCode::Char(c) => self.extension_input.push(c),
+            _ => {}
+        }
+        AppAction::Continue
+    }
+
+    /// Select every file in the current directory with `extension`
+    fn select_extension(&mut self, extension: &str) {
+        if extension.is_empty() {
+            return;
+        }
+        let matches: Vec<PathBuf> = self
+            .browser
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir && !e.is_invalid)
+            .filter(|e| {
+                e.path.extension().is_some_and(|ext| {
+                    let ext = ext.to_string_lossy();
+                    if self.extension_ignore_case {
+                        ext.to_lowercase() == extension.to_lowercase()
+                    } else {
+                        ext == extension
+                    }
+                })
+            })
+            .map(|e| e.path.clone())
+            .collect();
+        self.status_message = Some(format!("Selected {} .{} files", matches.len(), extension));
+        self.add_selection(matches);
+    }
+
+    /// Select every file in the current directory sharing the cursor
+    /// entry's stem, e.g. `foo.c`, `foo.h` and `foo.o`
+    fn select_same_stem(&mut self) {
+        let Some(stem) = self
+            .browser
+            .current_entry()
+            .filter(|e| !e.is_invalid)
+            .and_then(|e| e.path.file_stem())
+            .map(|s| s.to_os_string())
+        else {
+            return;
+        };
+        let matches: Vec<PathBuf> = self
+            .browser
+            .entries
+            .iter()
+            .filter(|e| !e.is_dir && !e.is_invalid)
+            .filter(|e| e.path.file_stem() == Some(stem.as_os_str()))
+            .map(|e| e.path.clone())
+            .collect();
+        self.status_message = Some(format!(
+            "Selected {} files named {}.*",
+            matches.len(),
+            stem.to_string_lossy()
+        ));
+        self.add_selection(matches);
+    }
+
+    fn run_command(&mut self, command: Command) -> Result<AppAction> {
+        if self.read_only && command.mutates() {
+            self.status_message = Some(READ_ONLY_MESSAGE.to_owned());
+            return Ok(AppAction::Continue);
+        }
+        match command {
+            Command::Select(path) => {
+                let path = self.browser.current_dir.join(path);
+                if !path.exists() {
+                    self.status_message = Some(format!("No such file: {}", path.display()));
+                } else if self.selection.is_selected(&path) {
+                    self.status_message = Some("Already selected".to_owned());
+                } else {
+                    self.add_selection(vec![path]);
+                    self.status_message = Some("Selected 1 path".to_owned());
+                }
+            }
+            Command::SelectRegex { pattern, recursive } => {
+                let matches: Vec<PathBuf> = if recursive {
+                    let dir = self.browser.current_dir.clone();
+                    self.browser
+                        .collect_files_recursive(&dir)
+                        .into_iter()
+                        .filter(|path| {
+                            path.strip_prefix(&dir)
+                                .is_ok_and(|rel| pattern.is_match(&rel.to_string_lossy()))
+                        })
+                        .collect()
+                } else {
+                    self.browser
+                        .entries
+                        .iter()
+                        .filter(|e| !e.is_invalid && pattern.is_match(&e.name))
+                        .map(|e| e.path.clone())
+                        .collect()
+                };
+                self.status_message = Some(format!("Selected {} matches", matches.len()));
+                self.add_selection(matches);
+            }
+            Command::SelectLargest { count, recursive } => {
+                let files: Vec<PathBuf> = if recursive {
+                    let dir = self.browser.current_dir.clone();
+                    self.browser.collect_files_recursive(&dir)
+                } else {
+                    self.browser
+                        .entries
+                        .iter()
+                        .filter(|e| !e.is_dir && !e.is_invalid)
+                        .map(|e| e.path.clone())
+                        .collect()
+                };
+                let mut sized: Vec<(u64, PathBuf)> = files
+                    .into_iter()
+                    .filter_map(|path| Some((fs::metadata(&path).ok()?.len(), path)))
+                    .collect();
+                sized.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+                sized.truncate(count);
+
+                let names: Vec<String> = sized
+                    .iter()
+                    .map(|(_, path)| display_relative(path, &self.browser.current_dir))
+                    .collect();
+                self.status_message = Some(match names.len() {
+                    0 => "No files to select".to_owned(),
+                    n => format!("Selected {} largest: {}", n, names.join(", ")),
+                });
+                self.add_selection(sized.into_iter().map(|(_, path)| path).collect());
+            }
+            Command::Deselect(path) => {
+                let full_path = self.browser.current_dir.join(&path);
+                if self.selection.is_selected(&full_path) {
+                    self.selection.remove_paths(&[full_path]);
+                    self.clamp_selected_cursor();
+                    self.status_message = Some("Deselected 1 path".to_owned());
+                } else if self.selection.remove_invalid_at(&full_path, &self.base_dir) {
+                    self.clamp_selected_cursor();
+                    self.status_message = Some("Deselected 1 path".to_owned());
+                } else {
+                    self.status_message = Some(format!("Not selected: {}", path.display()));
+                }
+            }
+            Command::SelectRange(range) => {
+                let paths: Vec<PathBuf> = self
+                    .entries_in(range)
+                    .filter(|e| !e.is_invalid && !self.selection.is_selected(&e.path))
+                    .map(|e| e.path.clone())
+                    .collect();
+                self.status_message = Some(format!(
+                    "Selected {} {}",
+                    paths.len(),
+                    crate::plural(paths.len(), "path", "paths")
+                ));
+                self.add_selection(paths);
+            }
+            Command::DeselectRange(range) => {
+                let paths: Vec<PathBuf> = self
+                    .entries_in(range)
+                    .filter(|e| self.selection.is_selected(&e.path))
+                    .map(|e| e.path.clone())
+                    .collect();
+                self.selection.remove_paths(&paths);
+                self.clamp_selected_cursor();
+                self.status_message = Some(format!(
+                    "Deselected {} {}",
+                    paths.len(),
+                    crate::plural(paths.len(), "path", "paths")
+                ));
+            }
+            Command::Cd(path) => {
+                let path = self.browser.current_dir.join(path);
+                if path.is_dir() {
+                    self.browser.goto(&path)?;
+                } else {
+                    self.status_message = Some(format!("Not a directory: {}", path.display()));
+                }
+            }
+            Command::Write => {
+                if self.can_save() {
+                    return Ok(AppAction::Save);
+                }
+                self.status_message = Some("No selections file to write (use -f)".to_owned());
+            }
+            Command::Quit => return Ok(AppAction::Quit),
+            Command::Hidden => self.browser.toggle_hidden()?,
+            Command::Reload => {
+                self.status_message = Some(match Config::reload() {
+                    Ok(Some(config)) => {
+                        self.apply_config(&config)?;
+                        "Reloaded profile; output, selection and start options need a restart"
+                            .to_owned()
+                    }
+                    Ok(None) => "Nothing to reload without --profile".to_owned(),
+                    Err(e) => format!("Reload failed: {}", e),
+                });
+            }
+        }
+        Ok(AppAction::Continue)
+    }
+
+    /// Take on the display and key settings of a reloaded configuration.
+    /// What decides the output or the selection stays as started.
+    fn apply_config(&mut self, config: &Config) -> Result<()> {
+        self.selection_style = config.selection_style;
+        self.dir_count = config.dir_count;
+        self.glyphs = config.glyphs();
+        self.size_thresholds = config.size_colors.then_some(config.size_thresholds);
+        self.icons = config.icons;
+        self.icon_overrides = config.icon_overrides.iter().cloned().collect();
+        self.show_mtime = config.show_mtime;
+        self.show_permissions = config.show_permissions;
+        self.selected_first = config.selected_first;
+        self.single_pane = config.single_pane;
+        self.scrollbar = !config.no_scrollbar;
+        self.color = config.color();
+        self.show_status = !config.no_status;
+        self.show_legend = !config.no_legend;
+        self.status_absolute = config.absolute_status;
+        self.confirm_threshold = config.confirm_threshold;
+        self.require_double_confirm = config.require_double_confirm;
+        self.space_recurses = config.space_recurses;
+        self.dir_enter = config.selected_dir_enter;
+        self.invalid_space = config.invalid_space;
+        self.type_ahead = config.type_ahead;
+        self.browser.set_listing(
+            config.show_hidden,
+            config.sort_options(),
+            config.hidden_patterns.clone(),
+        )
+    }
+
+    /// Files pane entries at the given positions, clamped to the listing
+    fn entries_in(&self, range: Range<usize>) -> impl Iterator<Item = &FileEntry> {
+        let end = range.end.min(self.browser.entries.len());
+        self.browser.entries[range.start.min(end)..end].iter()
+    }
+
+    /// Jump to the next entry starting with the keys typed so far. Repeating
+    /// a single key cycles through the entries starting with it instead.
+    fn type_ahead(&mut self, c: char) {
+        if self.last_type_ahead.elapsed() >= TYPE_AHEAD_TIMEOUT {
+            self.type_ahead_prefix.clear();
+        }
+        self.last_type_ahead = Instant::now();
+
+        let cycling = self.type_ahead_prefix.chars().all(|typed| typed == c);
+        self.type_ahead_prefix.push(c);
+        let (prefix, start) = if cycling {
+            (c.to_lowercase().to_string(), self.browser.cursor + 1)
+        } else {
+            (self.type_ahead_prefix.to_lowercase(), self.browser.cursor)
+        };
+
+        let entries = &self.browser.entries;
+        let found = (0..entries.len())
+            .map(|i| (start + i) % entries.len())
+            .find(|&i| entries[i].name.to_lowercase().starts_with(&prefix));
+        if let Some(pos) = found {
+            self.browser.cursor = pos;
+        }
+    }
+
     fn jump_to_match(&mut self) {
+        self.search_failed = false;
         if self.search_query.is_empty() {
             return;
         }
@@ -206,18 +1348,19 @@ impl App {
         {
             self.browser.cursor = pos;
             self.browser.scroll_offset = self.browser.scroll_offset.min(pos);
+            return;
         }
+
+        self.search_failed = true;
     }
 
     fn move_up(&mut self) {
         match self.focused_pane {
             FocusedPane::Files => self.browser.move_up(),
             FocusedPane::Selected => {
-                if self.selected_cursor > 0 {
-                    self.selected_cursor -= 1;
-                    // When moving up, keep cursor at top of visible area
-                    self.selected_scroll_offset = self.selected_scroll_offset.min(self.selected_cursor);
-                }
+                // Scrolling is settled in adjust_selected_scroll, which knows
+                // where group headers put the cursor row
+                self.selected_cursor = self.selected_cursor.saturating_sub(1);
             }
         }
     }
@@ -226,7 +1369,7 @@ impl App {
         match self.focused_pane {
             FocusedPane::Files => self.browser.move_down(),
             FocusedPane::Selected => {
-                let count = self.selection.count();
+                let count = self.shown_selected_count();
                 if count > 0 && self.selected_cursor + 1 < count {
                     self.selected_cursor += 1;
                 }
@@ -234,29 +1377,72 @@ impl App {
         }
     }
 
-    pub fn adjust_selected_scroll(&mut self, visible_height: usize) {
+    /// Adjust the Selected pane scroll so rows `top_row..=cursor_row` are
+    /// visible. Rows differ from item indices when grouping inserts headers.
+    pub fn adjust_selected_scroll(
+        &mut self,
+        top_row: usize,
+        cursor_row: usize,
+        visible_height: usize,
+    ) {
         if visible_height == 0 {
             return;
         }
+        // When moving up, keep cursor at top of visible area
+        if top_row < self.selected_scroll_offset {
+            self.selected_scroll_offset = top_row;
+        }
         // Ensure cursor is visible at bottom when scrolling down
-        if self.selected_cursor >= self.selected_scroll_offset + visible_height {
-            self.selected_scroll_offset = self.selected_cursor - visible_height + 1;
+        if cursor_row >= self.selected_scroll_offset + visible_height {
+            self.selected_scroll_offset = cursor_row - visible_height + 1;
         }
     }
 
     fn handle_space(&mut self) {
         match self.focused_pane {
+            FocusedPane::Files if self.space_recurses && self.cursor_on_directory() => {
+                self.toggle_recursive()
+            }
             FocusedPane::Files => self.toggle_current_entry(),
             FocusedPane::Selected => self.deselect_at_cursor(),
         }
     }
 
+    /// Toggle the entry under the cursor and move to the next one, for
+    /// marking runs of files quickly
+    fn toggle_and_advance(&mut self) {
+        if self.focused_pane == FocusedPane::Files {
+            self.toggle_current_entry();
+            self.browser.move_down();
+        }
+    }
+
+    fn cursor_on_directory(&self) -> bool {
+        self.browser
+            .current_entry()
+            .is_some_and(|entry| entry.is_dir && !entry.is_invalid)
+    }
+
     fn toggle_current_entry(&mut self) {
         let Some(entry) = self.browser.current_entry().cloned() else {
             return;
         };
 
-        if entry.is_invalid {
+        if entry.is_invalid && self.invalid_space != InvalidSpace::Toggle {
+            if self.invalid_space == InvalidSpace::Remove {
+                self.remove_invalid_entry(&entry);
+            }
+            return;
+        }
+
+        if self.single {
+            let selected = self.selection.is_selected(&entry.path)
+                || self.selection.is_invalid_selected(&entry.path);
+            self.selection.clear();
+            if !selected {
+                self.selection.add_paths([entry.path]);
+            }
+        } else if entry.is_invalid {
             // Invalid file is already in browser, just toggle selection state
             self.selection.toggle_invalid(&entry.path);
         } else {
@@ -264,6 +1450,30 @@ impl App {
         }
     }
 
+    /// Forget an invalid entry altogether, so it neither stays selected nor
+    /// comes back on the next refresh
+    fn remove_invalid_entry(&mut self, entry: &FileEntry) {
+        self.selection.remove_invalid(&entry.path);
+        self.browser.remove_invalid_path(&entry.path);
+        self.browser
+            .retain_entries(|e| !(e.is_invalid && e.path == entry.path));
+        self.status_message = Some(format!("Removed {}", entry.name));
+    }
+
+    /// Make the file under the cursor the one selected path, so Enter on it
+    /// confirms it in single mode. Directories are left to Space.
+    fn pick_current_file(&mut self) {
+        let Some(entry) = self.browser.current_entry().filter(|e| !e.is_dir) else {
+            return;
+        };
+        if self.selection.count() == 1 && self.selection.is_selected(&entry.path) {
+            return;
+        }
+        let path = entry.path.clone();
+        self.selection.clear();
+        self.selection.add_paths([path]);
+    }
+
     fn deselect_at_cursor(&mut self) {
         let items = self.get_selected_list();
         let Some((path, is_valid)) = items.get(self.selected_cursor).cloned() else {
@@ -279,6 +1489,22 @@ impl App {
         self.clamp_selected_cursor();
     }
 
+    fn select_current_dir(&mut self) {
+        let dir = self.browser.current_dir.clone();
+        let already_selected = self.selection.is_selected(&dir);
+        if !already_selected {
+            self.add_selection(vec![dir.clone()]);
+        }
+
+        let canonical = dir.canonicalize().unwrap_or(dir);
+        let display = self.format_path_for_display(&canonical, true);
+        self.status_message = Some(if already_selected {
+            format!("{} is already selected", display)
+        } else {
+            format!("Selected {}", display)
+        });
+    }
+
     fn toggle_recursive(&mut self) {
         let Some(entry) = self.browser.current_entry().cloned() else {
             return;
@@ -288,16 +1514,18 @@ impl App {
             return;
         }
 
-        let files = self.collect_files_recursive(&entry.path);
+        let files = self.browser.collect_files_recursive(&entry.path);
         if files.is_empty() {
             return;
         }
 
         let all_selected = files.iter().all(|f| self.selection.is_selected(f));
-        if all_selected {
+        if all_selected && files.len() > self.confirm_threshold {
+            self.pending_removal = Some(files);
+        } else if all_selected {
             self.selection.remove_paths(&files);
         } else {
-            self.selection.add_paths(files);
+            self.add_selection(files);
         }
     }
 
@@ -318,39 +1546,17 @@ impl App {
         if all_selected {
             self.selection.remove_paths(&paths);
         } else {
-            self.selection.add_paths(paths);
+            self.add_selection(paths);
         }
     }
 
-    fn collect_files_recursive(&self, dir: &Path) -> Vec<PathBuf> {
-        let Ok(entries) = fs::read_dir(dir) else {
-            return Vec::new();
-        };
-
-        entries
-            .filter_map(|e| e.ok())
-            .flat_map(|entry| {
-                let path = entry.path();
-                if path.is_dir() {
-                    self.collect_files_recursive(&path)
-                } else {
-                    let dominated_by_hidden = path
-                        .file_name()
-                        .map(|n| n.to_string_lossy().starts_with('.'))
-                        .unwrap_or(false);
-
-                    if self.browser.show_hidden || !dominated_by_hidden {
-                        vec![path]
-                    } else {
-                        vec![]
-                    }
-                }
-            })
-            .collect()
+    pub fn clear_selection(&mut self) {
+        self.selection.clear();
+        self.clamp_selected_cursor();
     }
 
     fn clamp_selected_cursor(&mut self) {
-        let count = self.selection.count();
+        let count = self.shown_selected_count();
         if count == 0 {
             self.selected_cursor = 0;
         } else if self.selected_cursor >= count {
@@ -358,13 +1564,25 @@ impl App {
         }
     }
 
-    /// Get sorted list of selected paths for display
+    /// How many selections the Selected pane lists under its filter
+    pub fn shown_selected_count(&self) -> usize {
+        let invalid = self.selection.invalid_count();
+        match self.selected_filter {
+            SelectedFilter::All => self.selection.count(),
+            SelectedFilter::Valid => self.selection.count() - invalid,
+            SelectedFilter::Invalid => invalid,
+        }
+    }
+
+    /// Get sorted list of selected paths for display, as filtered in the
+    /// Selected pane, so its indices match the pane's cursor
     pub fn get_selected_list(&self) -> Vec<(PathBuf, bool)> {
         let mut items: Vec<(PathBuf, bool)> = self
             .selection
             .iter_valid()
             .map(|p| (p.clone(), true))
             .chain(self.selection.iter_invalid().map(|p| (p.clone(), false)))
+            .filter(|(_, is_valid)| self.selected_filter.shows(*is_valid))
             .collect();
 
         items.sort_by(|a, b| {
@@ -376,21 +1594,379 @@ impl App {
     }
 
     pub fn format_path_for_display(&self, path: &Path, is_valid: bool) -> String {
-        if is_valid {
-            path.strip_prefix(&self.base_dir)
-                .map(|rel| format!("./{}", rel.display()))
-                .unwrap_or_else(|_| path.display().to_string())
+        let display = if is_valid {
+            display_relative(path, &self.base_dir)
+        } else if is_root(&self.base_dir) {
+            resolve_invalid(path, &self.base_dir).display().to_string()
         } else {
-            let s = path.to_string_lossy();
-            if s.starts_with("./") || s.starts_with('/') {
-                s.into_owned()
-            } else {
-                format!("./{}", s)
-            }
+            format_invalid_path(path, &self.base_dir, false)
+        };
+        self.tilde(display)
+    }
+
+    /// Abbreviate the home directory in an absolute display path, with --tilde
+    pub fn tilde(&self, display: String) -> String {
+        match self.output.home {
+            Some(ref home) => collapse_home(display, home),
+            None => display,
         }
     }
 
+    /// Whether confirming outputs absolute rather than relative paths
+    pub fn absolute_output(&self) -> bool {
+        self.output.absolute
+    }
+
     pub fn get_output(&self) -> Vec<String> {
-        self.selection.to_output(self.use_absolute, &self.base_dir)
+        self.selection.to_output(&self.output, &self.base_dir)
+    }
+
+    pub fn get_located_output(&self) -> Vec<(String, PathBuf)> {
+        self.selection
+            .to_located_output(&self.output, &self.base_dir)
+    }
+}
+
+/// Where browsing starts: the directory holding `--near`'s path, else
+/// `--start-dir`, else `base_dir` (the canonical current directory)
+pub fn browse_dir(base_dir: &Path, config: &Config) -> Result<PathBuf> {
+    Ok(match (&config.near, &config.start_dir) {
+        // The nearest existing directory holding the path
+        (Some(near), _) => base_dir
+            .join(near)
+            .ancestors()
+            .skip(1)
+            .find_map(|dir| dir.canonicalize().ok().filter(|dir| dir.is_dir()))
+            .unwrap_or_else(|| base_dir.to_path_buf()),
+        (None, Some(dir)) => dir
+            .canonicalize()
+            .ok()
+            .filter(|dir| dir.is_dir())
+            .ok_or_else(|| eyre!("start directory {} is not a directory", dir.display()))?,
+        (None, None) => base_dir.to_path_buf(),
+    })
+}
+
+/// Format an absolute path relative to `base_dir` as `./rel`. Paths outside
+/// `base_dir`, and every path when `base_dir` is the filesystem root (where
+/// `./usr` reads worse than `/usr`), are shown absolute instead.
+pub fn display_relative(path: &Path, base_dir: &Path) -> String {
+    if is_root(base_dir) {
+        return path.display().to_string();
+    }
+    path.strip_prefix(base_dir)
+        .map(|rel| format!("./{}", rel.display()))
+        .unwrap_or_else(|_| path.display().to_string())
+}
+
+/// Canonical path of a file that may not exist yet, through its directory
+fn canonical_location(file: &Path) -> Result<PathBuf> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = file.file_name().unwrap_or(file.as_os_str());
+    Ok(dir.canonicalize()?.join(name))
+}
+
+fn is_root(path: &Path) -> bool {
+    path.parent().is_none()
+}
+
+/// Normal-mode keys that change the selection or save it, or open a prompt
+/// that would
+fn is_mutating_key(code: KeyCode) -> bool {
+    matches!(
+        code,
+        KeyCode::Insert | KeyCode::Char(' ' | 'r' | 'R' | 'a' | ',' | 's' | 'x' | 'S' | 't')
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use clap::Parser;
+
+    fn app(dir: &TempDir, args: &[&str]) -> App {
+        app_selecting(dir, &[], args)
+    }
+
+    /// An App browsing `dir` with the `selected` paths (relative to it)
+    /// passed in as pre-selections
+    fn app_selecting(dir: &TempDir, selected: &[&str], args: &[&str]) -> App {
+        let config = Config::parse_from(std::iter::once("file-select").chain(args.iter().copied()));
+        let selected = selected.iter().map(|rel| dir.path().join(rel)).collect();
+        App::new(dir.path().to_owned(), selected, &config).unwrap()
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> AppAction {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+            .unwrap()
+    }
+
+    fn run(app: &mut App, line: &str) -> AppAction {
+        app.run_command(Command::parse(line).unwrap()).unwrap()
+    }
+
+    fn selected(app: &App) -> Vec<String> {
+        app.selection
+            .to_output(&OutputOptions::default(), &app.base_dir)
+    }
+
+    #[test]
+    fn select_regex_matches_names_or_relative_paths() {
+        let dir = TempDir::new("select-regex");
+        for file in ["a.rs", "b.txt", "sub/c.rs", "sub/d.txt"] {
+            dir.file(file);
+        }
+        let mut app = app(&dir, &[]);
+        run(&mut app, r"select-re \.rs$");
+        assert_eq!(selected(&app), ["./a.rs"]);
+        run(&mut app, "select-re -r ^sub/");
+        assert_eq!(selected(&app), ["./a.rs", "./sub/c.rs", "./sub/d.txt"]);
+    }
+
+    #[test]
+    fn root_base_dir_displays_absolute_paths() {
+        let dir = TempDir::new("root-display");
+        let file = dir.file("f");
+        let root = dir.path().ancestors().last().unwrap().to_owned();
+        let config = Config::parse_from(["file-select"]);
+        let mut app = App::new(root.clone(), Vec::new(), &config).unwrap();
+
+        assert_eq!(
+            app.format_path_for_display(&file, true),
+            file.display().to_string()
+        );
+        assert_eq!(
+            app.format_path_for_display(&root, true),
+            root.display().to_string()
+        );
+        let missing = Path::new("a").join("..").join("gone");
+        let resolved = root.join("gone").display().to_string();
+        assert_eq!(app.format_path_for_display(&missing, false), resolved);
+
+        assert!(!app.browser.go_parent().unwrap());
+        assert_eq!(app.browser.current_dir, root);
+        assert_eq!(display_relative(&dir.path().join("f"), dir.path()), "./f");
+    }
+
+    #[test]
+    fn selections_file_is_left_out_of_the_listing() {
+        let dir = TempDir::new("selections-listing");
+        let file = dir.file("picked.txt");
+        dir.file("other");
+        let file = file.to_str().unwrap();
+        let names = |app: &App| -> Vec<String> {
+            app.browser.entries.iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(names(&app(&dir, &["--file", file])), ["other"]);
+        let shown = app(&dir, &["--file", file, "--show-selections-file"]);
+        assert_eq!(names(&shown), ["other", "picked.txt"]);
+    }
+
+    #[test]
+    fn same_stem_selects_matching_files_only() {
+        let dir = TempDir::new("same-stem");
+        for file in [
+            "foo.c", "foo.h", "foo.o", "food.c", "bar.c", "foo/x", ".foo",
+        ] {
+            dir.file(file);
+        }
+        let mut app = app(&dir, &[]);
+        app.browser.move_to(&dir.path().join("foo.h"));
+        press(&mut app, KeyCode::Char('S'));
+        assert_eq!(selected(&app), ["./foo.c", "./foo.h", "./foo.o"]);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Selected 3 files named foo.*")
+        );
+    }
+
+    #[test]
+    fn space_on_invalid_entries_per_mode() {
+        let dir = TempDir::new("invalid-space");
+        dir.file("real");
+        let gone = dir.path().join("gone");
+        let listed = |app: &App| app.browser.entries.iter().any(|e| e.path == gone);
+        for (mode, selected, shown) in [
+            ("toggle", false, true),
+            ("remove", false, false),
+            ("ignore", true, true),
+        ] {
+            let mut app = app_selecting(&dir, &["gone"], &["--invalid-space", mode]);
+            app.browser.move_to(&gone);
+            press(&mut app, KeyCode::Char(' '));
+            app.browser.refresh().unwrap();
+            assert_eq!(
+                app.selection.is_invalid_selected(&gone),
+                selected,
+                "{}",
+                mode
+            );
+            assert_eq!(listed(&app), shown, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn read_only_refuses_mutation() {
+        let dir = TempDir::new("read-only");
+        for file in ["a", "b", "sub/c"] {
+            dir.file(file);
+        }
+        let mut app = app_selecting(&dir, &["a"], &["--read-only"]);
+        let keys = [' ', 'r', 'R', 'a', ',', 's', 'x', 'S', 't'].map(KeyCode::Char);
+        for pane in [FocusedPane::Files, FocusedPane::Selected] {
+            app.focused_pane = pane;
+            for key in keys.into_iter().chain([KeyCode::Insert]) {
+                app.status_message = None;
+                assert_eq!(press(&mut app, key), AppAction::Continue);
+                assert_eq!(
+                    app.status_message.as_deref(),
+                    Some(READ_ONLY_MESSAGE),
+                    "{:?}",
+                    key
+                );
+                assert_eq!(app.input_mode, InputMode::Normal);
+                assert_eq!(selected(&app), ["./a"]);
+            }
+        }
+        assert_eq!(run(&mut app, "select b"), AppAction::Continue);
+        assert_eq!(app.status_message.as_deref(), Some(READ_ONLY_MESSAGE));
+
+        // Browsing still works, and confirming emits the pre-selection
+        app.focused_pane = FocusedPane::Files;
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.browser.cursor, 1);
+        assert_eq!(press(&mut app, KeyCode::Enter), AppAction::Confirm);
+        assert_eq!(app.get_output(), ["./a"]);
+    }
+
+    #[test]
+    fn selected_filter_maps_the_cursor_to_shown_entries() {
+        let dir = TempDir::new("selected-filter");
+        dir.file("a");
+        dir.file("c");
+        let mut app = app_selecting(&dir, &["a", "c", "gone1", "gone2"], &[]);
+        let shown = |app: &App| -> Vec<PathBuf> {
+            app.get_selected_list()
+                .into_iter()
+                .map(|(path, _)| path)
+                .collect()
+        };
+        let path = |name: &str| dir.path().join(name);
+        app.focused_pane = FocusedPane::Selected;
+
+        press(&mut app, KeyCode::Char('v'));
+        assert_eq!(app.selected_filter, SelectedFilter::Valid);
+        assert_eq!(shown(&app), [path("a"), path("c")]);
+        app.selected_cursor = 1;
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(shown(&app), [path("a")]);
+        assert_eq!(app.selected_cursor, 0);
+
+        press(&mut app, KeyCode::Char('v'));
+        assert_eq!(app.selected_filter, SelectedFilter::Invalid);
+        assert_eq!(shown(&app), [path("gone1"), path("gone2")]);
+        assert_eq!(app.shown_selected_count(), 2);
+        app.selected_cursor = 1;
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(shown(&app), [path("gone1")]);
+
+        press(&mut app, KeyCode::Char('v'));
+        assert_eq!(app.selected_filter, SelectedFilter::All);
+        assert_eq!(shown(&app), [path("a"), path("gone1")]);
+    }
+
+    #[test]
+    fn select_all_stops_at_the_limit() {
+        let dir = TempDir::new("max-select");
+        for file in ["a", "b", "c", "d", "e"] {
+            dir.file(file);
+        }
+        let mut app = app_selecting(&dir, &["e"], &["--max-select", "3"]);
+        press(&mut app, KeyCode::Char('a'));
+        assert_eq!(selected(&app), ["./a", "./b", "./e"]);
+        assert_eq!(app.status_message.as_deref(), Some("Limit reached (3)"));
+
+        // Toggling one more is refused too, until something is deselected
+        app.browser.move_to(&dir.path().join("d"));
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(selected(&app).len(), 3);
+        app.browser.move_to(&dir.path().join("a"));
+        press(&mut app, KeyCode::Char(' '));
+        app.browser.move_to(&dir.path().join("d"));
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(selected(&app), ["./b", "./d", "./e"]);
+    }
+
+    #[test]
+    fn confirm_needs_the_minimum() {
+        let dir = TempDir::new("min-select");
+        for file in ["a", "b"] {
+            dir.file(file);
+        }
+        let mut app = app(&dir, &["--min-select", "2"]);
+        assert_eq!(press(&mut app, KeyCode::Enter), AppAction::Continue);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Need at least 2 (have 0)")
+        );
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(press(&mut app, KeyCode::Enter), AppAction::Continue);
+        assert_eq!(
+            app.status_message.as_deref(),
+            Some("Need at least 2 (have 1)")
+        );
+        app.browser.move_to(&dir.path().join("b"));
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(press(&mut app, KeyCode::Enter), AppAction::Confirm);
+        assert_eq!(app.get_output(), ["./a", "./b"]);
+    }
+
+    #[test]
+    fn selected_entries_float_to_the_top() {
+        let dir = TempDir::new("selected-first");
+        for file in ["a", "b", "c", "d", "x/inner", "y/inner"] {
+            dir.file(file);
+        }
+        let mut app = app_selecting(&dir, &["c", "y", "gone"], &["--selected-first"]);
+        let names = |app: &App| -> Vec<String> {
+            app.browser.entries.iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(names(&app), ["y", "c", "gone", "x", "a", "b", "d"]);
+
+        // The cursor follows the entry it was on as selecting moves it up
+        app.browser.move_to(&dir.path().join("b"));
+        press(&mut app, KeyCode::Char(' '));
+        assert_eq!(names(&app), ["y", "b", "c", "gone", "x", "a", "d"]);
+        assert_eq!(app.browser.current_entry().unwrap().name, "b");
+
+        press(&mut app, KeyCode::Char('F'));
+        assert_eq!(names(&app), ["x", "y", "a", "b", "c", "d", "gone"]);
+        assert_eq!(app.browser.current_entry().unwrap().name, "b");
+    }
+
+    #[test]
+    fn index_ranges_clamp_to_the_listing() {
+        let dir = TempDir::new("index-range");
+        for file in ["a", "b", "c", "d", "sub/x"] {
+            dir.file(file);
+        }
+        // Listed as sub, a, b, c, d, gone
+        let mut app = app_selecting(&dir, &["gone"], &[]);
+        run(&mut app, "select 2-3");
+        assert_eq!(app.status_message.as_deref(), Some("Selected 2 paths"));
+        run(&mut app, "select 3-100");
+        assert_eq!(app.status_message.as_deref(), Some("Selected 2 paths"));
+        let gone = dir.path().join("gone").display().to_string();
+        assert_eq!(selected(&app), ["./a", "./b", "./c", "./d", gone.as_str()]);
+
+        run(&mut app, "deselect 1-2");
+        assert_eq!(app.status_message.as_deref(), Some("Deselected 1 path"));
+        run(&mut app, "select 7-9");
+        assert_eq!(app.status_message.as_deref(), Some("Selected 0 paths"));
+        assert_eq!(selected(&app), ["./b", "./c", "./d", gone.as_str()]);
     }
 }
@@ -0,0 +1,119 @@
+//! `--checksums` output: a `sha256sum`-style manifest of the selected files.
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::bail;
+use color_eyre::Result;
+use sha2::{Digest, Sha256};
+
+use crate::log::verbose;
+use crate::platform;
+
+/// Write `<sha256>  <path>` for each output line whose location is a file.
+/// Directories and invalid paths have nothing to hash and are skipped, and
+/// files that can't be read are reported on stderr and left out. Progress
+/// goes to stderr when it is a terminal, and an interrupt stops hashing
+/// midway.
+pub fn write_manifest(out: &mut impl Write, items: &[(String, PathBuf)]) -> Result<()> {
+    let files: Vec<&(String, PathBuf)> = items.iter().filter(|(_, loc)| loc.is_file()).collect();
+    verbose!(
+        1,
+        "checksums: hashing {} files, skipping {}",
+        files.len(),
+        items.len() - files.len()
+    );
+
+    let mut progress = io::stderr().is_terminal().then(io::stderr);
+    let result = hash_files(out, &files, &mut progress);
+    if let Some(ref mut stderr) = progress {
+        // Clear the progress line, also when stopping early
+        write!(stderr, "\r\x1b[K")?;
+    }
+    result
+}
+
+fn hash_files(
+    out: &mut impl Write,
+    files: &[&(String, PathBuf)],
+    progress: &mut Option<io::Stderr>,
+) -> Result<()> {
+    for (done, (path, location)) in files.iter().enumerate() {
+        if platform::interrupted() {
+            bail!(
+                "interrupted after hashing {} of {} files",
+                done,
+                files.len()
+            );
+        }
+        if let Some(ref mut stderr) = progress {
+            write!(stderr, "\rHashing {}/{}", done + 1, files.len())?;
+        }
+        match sha256_file(location) {
+            Ok(digest) => writeln!(out, "{}  {}", digest, path)?,
+            Err(err) => {
+                if let Some(ref mut stderr) = progress {
+                    write!(stderr, "\r\x1b[K")?;
+                }
+                eprintln!("checksums: skipping {}: {}", location.display(), err);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Hex SHA-256 of a file's contents
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn manifest_hashes_files_only() {
+        let dir = TempDir::new("checksums");
+        let abc = dir.file("abc");
+        std::fs::write(&abc, "abc").unwrap();
+        let empty = dir.file("sub/empty");
+        let items = [
+            ("./abc".to_owned(), abc),
+            ("./gone".to_owned(), dir.path().join("gone")),
+            ("./sub".to_owned(), dir.path().join("sub")),
+            ("./sub/empty".to_owned(), empty),
+        ];
+        let mut out = Vec::new();
+        write_manifest(&mut out, &items).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  ./abc\n\
+             e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  ./sub/empty\n"
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn manifest_skips_unreadable_files() {
+        // A regular file whose reads fail with EIO, even as root, where a
+        // chmod 000 file would still be readable
+        let unreadable = PathBuf::from("/proc/self/mem");
+        let dir = TempDir::new("checksums-unreadable");
+        let abc = dir.file("abc");
+        std::fs::write(&abc, "abc").unwrap();
+        let items = [
+            ("/proc/self/mem".to_owned(), unreadable),
+            ("./abc".to_owned(), abc),
+        ];
+        let mut out = Vec::new();
+        write_manifest(&mut out, &items).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  ./abc\n"
+        );
+    }
+}
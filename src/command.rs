@@ -0,0 +1,225 @@
+use std::ops::Range;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+/// A command entered at the `:` prompt
+#[derive(Debug, Clone)]
+pub enum Command {
+    Select(PathBuf),
+    Deselect(PathBuf),
+    /// Select the Files pane entries at these (0-based) positions, written
+    /// 1-based and inclusive as `:select 3-10`
+    SelectRange(Range<usize>),
+    DeselectRange(Range<usize>),
+    /// Select entries whose name matches, or with `recursive` every file
+    /// below the current directory whose relative path matches
+    SelectRegex {
+        pattern: Regex,
+        recursive: bool,
+    },
+    /// Select the `count` largest files in the current directory, or with
+    /// `recursive` anywhere below it
+    SelectLargest {
+        count: usize,
+        recursive: bool,
+    },
+    Cd(PathBuf),
+    Write,
+    Quit,
+    Hidden,
+    /// Re-read the `--profile` and apply its display settings
+    Reload,
+}
+
+impl Command {
+    /// Parse a command line (without the leading `:`). Returns a message
+    /// suitable for the status bar on failure.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let (name, arg) = match input.split_once(char::is_whitespace) {
+            Some((name, arg)) => (name, arg.trim()),
+            None => (input, ""),
+        };
+
+        match name {
+            "select" | "sel" => match index_range(name, arg) {
+                Some(range) => Ok(Self::SelectRange(range?)),
+                None => Ok(Self::Select(required_path(name, arg)?)),
+            },
+            "deselect" | "desel" => match index_range(name, arg) {
+                Some(range) => Ok(Self::DeselectRange(range?)),
+                None => Ok(Self::Deselect(required_path(name, arg)?)),
+            },
+            "select-re" => parse_select_regex(arg),
+            "select-largest" => parse_select_largest(arg),
+            "cd" => Ok(Self::Cd(required_path(name, arg)?)),
+            "write" | "w" => no_argument(name, arg, Self::Write),
+            "quit" | "q" => no_argument(name, arg, Self::Quit),
+            "hidden" => no_argument(name, arg, Self::Hidden),
+            "reload" => no_argument(name, arg, Self::Reload),
+            "" => Err("Empty command".to_owned()),
+            _ => Err(format!("Unknown command: {}", name)),
+        }
+    }
+}
+
+impl Command {
+    /// Whether the command changes the selection or writes it out, which
+    /// `--read-only` refuses
+    pub fn mutates(&self) -> bool {
+        matches!(
+            self,
+            Self::Select(_)
+                | Self::Deselect(_)
+                | Self::SelectRange(_)
+                | Self::DeselectRange(_)
+                | Self::SelectRegex { .. }
+                | Self::SelectLargest { .. }
+                | Self::Write
+        )
+    }
+}
+
+fn parse_select_regex(arg: &str) -> Result<Command, String> {
+    let (recursive, pattern) = match arg.strip_prefix("-r ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, arg),
+    };
+    if pattern.is_empty() {
+        return Err(":select-re needs a pattern".to_owned());
+    }
+
+    let pattern = Regex::new(pattern).map_err(|e| {
+        // Syntax errors span several lines; the last one names the problem
+        let message = e.to_string();
+        format!(
+            "Invalid regex: {}",
+            message.lines().last().unwrap_or_default()
+        )
+    })?;
+    Ok(Command::SelectRegex { pattern, recursive })
+}
+
+fn parse_select_largest(arg: &str) -> Result<Command, String> {
+    let (recursive, count) = match arg.strip_prefix("-r ") {
+        Some(rest) => (true, rest.trim_start()),
+        None => (false, arg),
+    };
+    match count.parse() {
+        Ok(count) if count > 0 => Ok(Command::SelectLargest { count, recursive }),
+        _ => Err(":select-largest needs a positive count".to_owned()),
+    }
+}
+
+/// Read `N-M` as 1-based inclusive positions, or `None` if the argument
+/// isn't shaped like a range and should be taken as a path instead (so a
+/// file named `3-10` is reached as `./3-10`)
+fn index_range(name: &str, arg: &str) -> Option<Result<Range<usize>, String>> {
+    let (start, end) = arg.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    let end: usize = end.parse().ok()?;
+    Some(if start == 0 {
+        Err(format!(":{} positions start at 1", name))
+    } else if start > end {
+        Err(format!(":{} range {} is backwards", name, arg))
+    } else {
+        Ok(start - 1..end)
+    })
+}
+
+fn required_path(name: &str, arg: &str) -> Result<PathBuf, String> {
+    if arg.is_empty() {
+        Err(format!(":{} needs a path", name))
+    } else {
+        Ok(PathBuf::from(arg))
+    }
+}
+
+fn no_argument(name: &str, arg: &str, command: Command) -> Result<Command, String> {
+    if arg.is_empty() {
+        Ok(command)
+    } else {
+        Err(format!(":{} takes no arguments", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn parse_err(input: &str) -> String {
+        Command::parse(input).unwrap_err()
+    }
+
+    #[test]
+    fn paths_are_the_rest_of_the_line() {
+        let Ok(Command::Select(path)) = Command::parse("  select  my file.txt ") else {
+            panic!("expected :select");
+        };
+        assert_eq!(path, Path::new("my file.txt"));
+        // No quoting: quotes are part of the name
+        let Ok(Command::Deselect(path)) = Command::parse("desel 'a b'") else {
+            panic!("expected :deselect");
+        };
+        assert_eq!(path, Path::new("'a b'"));
+        let Ok(Command::Cd(path)) = Command::parse("cd ..") else {
+            panic!("expected :cd");
+        };
+        assert_eq!(path, Path::new(".."));
+    }
+
+    #[test]
+    fn unknown_and_empty_commands() {
+        assert_eq!(parse_err("frobnicate now"), "Unknown command: frobnicate");
+        assert_eq!(parse_err("   "), "Empty command");
+    }
+
+    #[test]
+    fn missing_and_extra_arguments() {
+        assert_eq!(parse_err("select"), ":select needs a path");
+        assert_eq!(parse_err("cd  "), ":cd needs a path");
+        assert_eq!(parse_err("select-re"), ":select-re needs a pattern");
+        assert_eq!(
+            parse_err("select-largest 0"),
+            ":select-largest needs a positive count"
+        );
+        assert_eq!(parse_err("w now"), ":w takes no arguments");
+        assert!(matches!(Command::parse("quit"), Ok(Command::Quit)));
+    }
+
+    #[test]
+    fn regex_errors_name_the_problem() {
+        assert!(parse_err("select-re (").starts_with("Invalid regex: "));
+        let Ok(Command::SelectRegex {
+            pattern,
+            recursive: true,
+        }) = Command::parse("select-re -r \\.rs$")
+        else {
+            panic!("expected recursive :select-re");
+        };
+        assert!(pattern.is_match("src/main.rs"));
+    }
+
+    #[test]
+    fn index_ranges() {
+        let select = Command::parse("select 3-10");
+        assert!(matches!(select, Ok(Command::SelectRange(r)) if r == (2..10)));
+        let deselect = Command::parse("deselect 1-1");
+        assert!(matches!(deselect, Ok(Command::DeselectRange(r)) if r == (0..1)));
+        assert_eq!(parse_err("select 0-3"), ":select positions start at 1");
+        assert_eq!(
+            parse_err("deselect 5-2"),
+            ":deselect range 5-2 is backwards"
+        );
+        // Anything else is a path that happens to contain a dash
+        for arg in ["a-b", "3-", "-3", "1-2-3", "3 - 4"] {
+            let Ok(Command::Select(path)) = Command::parse(&format!("select {}", arg)) else {
+                panic!("expected :select of the path {}", arg);
+            };
+            assert_eq!(path, Path::new(arg));
+        }
+    }
+}
@@ -1,15 +1,40 @@
+use std::ffi::OsString;
+use std::fs;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::Parser;
+use color_eyre::eyre::{bail, eyre, WrapErr};
+use color_eyre::Result;
+
+use crate::app::{DirEnter, InvalidSpace};
+use crate::file_browser::SortOptions;
+use crate::platform;
+use crate::selection::{OutputFilter, OutputOptions, OutputSort};
+use crate::ui::{DirCount, Glyphs, SelectionStyle};
+
+/// Profiles live in this subdirectory of the configuration directory
+const PROFILE_DIR: &str = "file-select/profiles";
 
 #[derive(Parser, Debug)]
 #[command(name = "file-list")]
 #[command(about = "TUI file selector with vim-style navigation")]
+// Options given on the command line replace those from a profile
+#[command(args_override_self = true)]
 pub struct Config {
     /// Output absolute paths instead of relative
     #[arg(short = 'a', long = "absolute")]
     pub absolute: bool,
 
+    /// Output paths relative to the deepest directory containing all selections
+    #[arg(long = "rel-common", conflicts_with = "absolute")]
+    pub rel_common: bool,
+
+    /// Output paths inside a git repository relative to that repository's root
+    #[arg(long = "git-relative", conflicts_with_all = ["absolute", "rel_common"])]
+    pub git_relative: bool,
+
     /// Output relative paths (default)
     #[arg(short = 'r', long = "relative")]
     pub relative: bool,
@@ -18,17 +43,556 @@ pub struct Config {
     #[arg(short = 'H', long = "hidden")]
     pub show_hidden: bool,
 
+    /// List directories before files (default)
+    #[arg(long = "dirs-first", overrides_with = "no_dirs_first")]
+    pub dirs_first: bool,
+
+    /// Sort directories and files together
+    #[arg(
+        long = "no-dirs-first",
+        alias = "no-dir-first",
+        overrides_with = "dirs_first"
+    )]
+    pub no_dirs_first: bool,
+
+    /// Also treat names matching this glob as hidden (repeatable, e.g. '*~')
+    #[arg(long = "hidden-pattern", value_name = "GLOB")]
+    pub hidden_patterns: Vec<String>,
+
+    /// List selected entries before the rest in the Files pane (toggle with F)
+    #[arg(long = "selected-first")]
+    pub selected_first: bool,
+
+    /// Sort names strictly lexically instead of comparing numbers by value
+    #[arg(long = "lexical-sort")]
+    pub lexical_sort: bool,
+
+    /// Show the absolute current directory in the status bar
+    #[arg(long = "absolute-status")]
+    pub absolute_status: bool,
+
+    /// Show modification times ("3d ago") in the Files pane
+    #[arg(long = "show-mtime")]
+    pub show_mtime: bool,
+
+    /// Show octal permissions and owner/group in the Files pane (Unix only)
+    #[arg(long = "show-permissions")]
+    pub show_permissions: bool,
+
+    /// Read at most N entries of a directory at first, loading more with L
+    #[arg(long = "page-size", value_name = "N")]
+    pub page_size: Option<NonZeroUsize>,
+
+    /// What Enter does on a selected directory in the Selected pane
+    #[arg(
+        long = "selected-dir-enter",
+        value_name = "ACTION",
+        value_enum,
+        default_value_t = DirEnter::Navigate
+    )]
+    pub selected_dir_enter: DirEnter,
+
+    /// What Space does on an invalid (missing) entry in the Files pane
+    #[arg(
+        long = "invalid-space",
+        value_name = "ACTION",
+        value_enum,
+        default_value_t = InvalidSpace::Toggle
+    )]
+    pub invalid_space: InvalidSpace,
+
+    /// Make Space on a directory toggle its contents recursively, like r
+    #[arg(long = "space-recurses")]
+    pub space_recurses: bool,
+
+    /// Show one pane at a time, swapped with Tab (automatic on narrow terminals
+    /// and with --single)
+    #[arg(long = "single-pane")]
+    pub single_pane: bool,
+
+    /// Don't draw scrollbars on lists longer than their pane
+    #[arg(long = "no-scrollbar")]
+    pub no_scrollbar: bool,
+
+    /// Draw without colors, marking the cursor and selections by text and
+    /// reverse video alone (also when NO_COLOR is set)
+    #[arg(long = "no-color")]
+    pub no_color: bool,
+
+    /// Hide the status bar except while it shows a prompt or message (F1 cycles)
+    #[arg(long = "no-status")]
+    pub no_status: bool,
+
+    /// Hide the key legend at the bottom (F1 cycles)
+    #[arg(long = "no-legend")]
+    pub no_legend: bool,
+
+    /// Jump to entries by typing the start of their name with keys that aren't bound
+    #[arg(long = "type-ahead")]
+    pub type_ahead: bool,
+
+    /// Browse only: refuse keys and commands that change or save the selection
+    #[arg(long = "read-only")]
+    pub read_only: bool,
+
+    /// How selected entries are marked in the Files pane
+    #[arg(
+        long = "selection-style",
+        value_name = "STYLE",
+        value_enum,
+        default_value_t = SelectionStyle::Checkbox
+    )]
+    pub selection_style: SelectionStyle,
+
+    /// Which selections a directory's count covers: all below it, direct children, or both
+    #[arg(long = "dir-count", value_name = "WHICH", value_enum, default_value_t = DirCount::Total)]
+    pub dir_count: DirCount,
+
+    /// Cursor symbol in the lists
+    #[arg(long = "cursor-glyph", value_name = "TEXT", default_value = ">")]
+    pub cursor_glyph: String,
+
+    /// Checkbox of selected entries, e.g. ●
+    #[arg(long = "checked-glyph", value_name = "TEXT", default_value = "[x]")]
+    pub checked_glyph: String,
+
+    /// Checkbox of unselected entries, e.g. ○
+    #[arg(long = "unchecked-glyph", value_name = "TEXT", default_value = "[ ]")]
+    pub unchecked_glyph: String,
+
+    /// Marker of selected entries with --selection-style marker
+    #[arg(long = "marker-glyph", value_name = "TEXT", default_value = "*")]
+    pub marker_glyph: String,
+
+    /// Audit the pre-selections against the tree: missing paths show red and
+    /// files on disk that aren't listed show magenta
+    #[arg(long = "diff")]
+    pub diff: bool,
+
+    /// Color files by size: green, yellow, then red from the second threshold
+    #[arg(long = "size-colors")]
+    pub size_colors: bool,
+
+    /// Sizes where --size-colors turns yellow and red (K, M, G suffixes)
+    #[arg(
+        long = "size-thresholds",
+        value_name = "YELLOW,RED",
+        default_value = "1M,100M",
+        value_parser = parse_size_thresholds
+    )]
+    pub size_thresholds: (u64, u64),
+
+    /// Prefix entries with Nerd Font file-type icons (needs a patched font)
+    #[arg(long = "icons")]
+    pub icons: bool,
+
+    /// Use ICON for files with extension EXT under --icons (repeatable)
+    #[arg(long = "icon", value_name = "EXT=ICON", value_parser = parse_icon)]
+    pub icon_overrides: Vec<(String, String)>,
+
+    /// Append a trailing slash to directory paths in the output
+    #[arg(long = "dir-slash")]
+    pub dir_slash: bool,
+
+    /// Only output selections that exist
+    #[arg(long = "valid-only", conflicts_with = "invalid_only")]
+    pub valid_only: bool,
+
+    /// Only output selections that don't exist
+    #[arg(long = "invalid-only")]
+    pub invalid_only: bool,
+
+    /// Refuse to select more than N paths
+    #[arg(long = "max-select", value_name = "N")]
+    pub max_select: Option<usize>,
+
+    /// Refuse to confirm until at least N paths are selected
+    #[arg(long = "min-select", value_name = "N", default_value_t = 0)]
+    pub min_select: usize,
+
+    /// Ask before a recursive deselect removes more than this many files
+    #[arg(long = "confirm-threshold", value_name = "N", default_value_t = 100)]
+    pub confirm_threshold: usize,
+
+    /// Order of the emitted paths
+    #[arg(long = "sort-output", value_name = "KEY", value_enum, default_value_t = OutputSort::Name)]
+    pub sort_output: OutputSort,
+
+    /// Emit paths in descending order (e.g. newest first with --sort-output mtime)
+    #[arg(long = "reverse-output")]
+    pub reverse_output: bool,
+
+    /// Output the distinct directories that contain selections
+    #[arg(long = "emit-dirs")]
+    pub emit_dirs: bool,
+
+    /// Single-quote each output path for safe reuse in a shell
+    #[arg(long = "shell-quote")]
+    pub shell_quote: bool,
+
+    /// Print the paths to stdout as a bash/zsh array assignment, NAME=( 'a' 'b' )
+    #[arg(
+        long = "bash-array",
+        value_name = "NAME",
+        value_parser = parse_variable_name,
+        conflicts_with = "shell_quote"
+    )]
+    pub bash_array: Option<String>,
+
+    /// Output each selection as its relative path, a tab, then its absolute path
+    #[arg(long = "both", conflicts_with_all = ["absolute", "bash_array"])]
+    pub both: bool,
+
+    /// Abbreviate absolute paths under the home directory as ~/...
+    #[arg(long = "tilde", conflicts_with_all = ["shell_quote", "bash_array"])]
+    pub tilde: bool,
+
+    /// Output paths as selected, through symlinks, instead of resolving them
+    #[arg(long = "no-resolve-symlinks")]
+    pub no_resolve_symlinks: bool,
+
+    /// Still output a non-empty selection when quitting without confirming,
+    /// then exit with status 2
+    #[arg(long = "output-on-cancel")]
+    pub output_on_cancel: bool,
+
+    /// Print this line before the paths written to stdout
+    #[arg(long = "header", value_name = "TEXT")]
+    pub header: Option<String>,
+
+    /// Confirm only when Enter is pressed twice in a row
+    #[arg(long = "require-double-confirm")]
+    pub require_double_confirm: bool,
+
+    /// Print only the number of paths that would be output instead of the paths
+    #[arg(long = "count", conflicts_with_all = ["selections_file", "bash_array", "header", "both"])]
+    pub count: bool,
+
+    /// Print `<sha256>  <path>` lines, like sha256sum, for the selected files
+    #[arg(
+        long = "checksums",
+        conflicts_with_all = ["selections_file", "bash_array", "count", "both"]
+    )]
+    pub checksums: bool,
+
+    /// With --count, print 0 when quitting without confirming instead of nothing
+    #[arg(long = "count-zero-on-cancel", requires = "count")]
+    pub count_zero_on_cancel: bool,
+
+    /// Print a count of the confirmed files, directories and invalid paths to stderr
+    #[arg(long = "summary")]
+    pub summary: bool,
+
+    /// Keep running after Enter: each confirm emits a batch, q finishes.
+    /// Not with --summary or --checksums, which would write to stderr under
+    /// the running UI.
+    #[arg(long = "loop", conflicts_with_all = ["summary", "checksums"])]
+    pub loop_mode: bool,
+
+    /// With --loop, clear the selection after each batch
+    #[arg(long = "loop-clear", requires = "loop_mode")]
+    pub loop_clear: bool,
+
+    /// Cancel (as if q was pressed) after this many seconds without input
+    #[arg(long = "timeout", value_name = "SECONDS")]
+    pub timeout: Option<u64>,
+
+    /// Start in search mode with this query typed, on its first match
+    #[arg(long = "search", value_name = "QUERY")]
+    pub search: Option<String>,
+
+    /// Stop reading piped pre-selections once stdin has been silent this long,
+    /// e.g. 0.5, instead of waiting for it to close
+    #[arg(long = "stdin-timeout", value_name = "SECONDS", value_parser = parse_seconds)]
+    pub stdin_timeout: Option<Duration>,
+
+    /// Terminal to draw the TUI on (default /dev/tty or CONOUT$, falling back to stderr)
+    #[arg(long = "tty", value_name = "PATH")]
+    pub tty: Option<PathBuf>,
+
+    /// Start browsing here instead of the current directory, which output
+    /// paths stay relative to
+    #[arg(long = "start-dir", value_name = "DIR", env = "FILE_SELECT_START_DIR")]
+    pub start_dir: Option<PathBuf>,
+
+    /// Start browsing in this path's directory with the cursor on it, without
+    /// selecting it (missing paths open their nearest existing ancestor).
+    /// Overrides `--start-dir`, which may come from the environment.
+    #[arg(long = "near", value_name = "PATH")]
+    pub near: Option<PathBuf>,
+
+    /// Expand a leading ~ and $VAR / ${VAR} in pre-selected paths from the
+    /// arguments, stdin and -f
+    #[arg(long = "expand-env")]
+    pub expand_env: bool,
+
+    /// Don't pre-select the paths listed in ./.fileselect
+    #[arg(long = "no-project")]
+    pub no_project: bool,
+
+    /// Start from the options saved in the named profile file
+    #[arg(long = "profile", value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// List the selections file in the Files pane instead of hiding it
+    #[arg(long = "show-selections-file")]
+    pub show_selections_file: bool,
+
+    /// Exit with an error instead of starting if a pre-selected path doesn't exist
+    #[arg(long = "strict")]
+    pub strict: bool,
+
+    /// Pick a single path: selecting replaces the selection, Enter on a file
+    /// confirms it, on a directory opens it. Implies --single-pane
+    #[arg(long = "single")]
+    pub single: bool,
+
     /// Selections file to read from and write to
     #[arg(short = 'f', long = "file")]
     pub selections_file: Option<PathBuf>,
 
+    /// Print diagnostics to stderr (repeat for more detail)
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
     /// Pre-selected files
     #[arg(value_name = "FILES")]
     pub files: Vec<PathBuf>,
 }
 
+/// Accept only valid shell variable names, since the name is `eval`ed
+fn parse_variable_name(name: &str) -> Result<String, String> {
+    let mut chars = name.chars();
+    let valid_start = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+    if valid_start && chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Ok(name.to_owned())
+    } else {
+        Err("must be a shell variable name (letters, digits and _)".to_owned())
+    }
+}
+
+/// Split an `EXT=ICON` override; extensions match case-insensitively
+fn parse_icon(value: &str) -> Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((ext, icon)) if !ext.is_empty() && !icon.is_empty() => {
+            Ok((ext.trim_start_matches('.').to_lowercase(), icon.to_owned()))
+        }
+        _ => Err("expected EXT=ICON".to_owned()),
+    }
+}
+
+/// The command line with the options of profile `name` inserted after the
+/// program name
+fn with_profile(name: &str, args: &[OsString]) -> Result<Vec<OsString>> {
+    let path = platform::config_dir()
+        .ok_or_else(|| eyre!("cannot find the configuration directory for --profile"))?
+        .join(PROFILE_DIR)
+        .join(name);
+    let text = fs::read_to_string(&path)
+        .wrap_err_with(|| format!("cannot read profile {} ({})", name, path.display()))?;
+
+    let mut merged = args[..1].to_vec();
+    merged.extend(profile_args(&text).map(OsString::from));
+    merged.extend_from_slice(&args[1..]);
+    Ok(merged)
+}
+
+/// Options in a profile file: one per line, `--name` or `--name value`
+/// (the value is the rest of the line, spaces included). Blank lines and
+/// lines starting with `#` are skipped.
+fn profile_args(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .flat_map(|line| match line.split_once(char::is_whitespace) {
+            Some((option, value)) => vec![option, value.trim_start()],
+            None => vec![line],
+        })
+}
+
+/// The canonical home directory, as selections are stored canonical
+fn home_dir() -> Option<PathBuf> {
+    std::env::home_dir()?.canonicalize().ok()
+}
+
+/// A non-negative, possibly fractional number of seconds
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid number of seconds '{}'", value))
+}
+
+/// Parse `YELLOW,RED` as two ascending sizes
+fn parse_size_thresholds(value: &str) -> Result<(u64, u64), String> {
+    let (yellow, red) = value
+        .split_once(',')
+        .ok_or("expected YELLOW,RED, e.g. 1M,100M")?;
+    let (yellow, red) = (parse_size(yellow)?, parse_size(red)?);
+    if yellow > red {
+        return Err("the yellow threshold must not exceed the red one".to_owned());
+    }
+    Ok((yellow, red))
+}
+
+/// A byte count with an optional binary K, M or G suffix (a trailing B or
+/// iB is accepted too), e.g. `512`, `10K` or `1.5GiB`
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let digits = value.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit = &value[digits.len()..];
+    let unit = unit
+        .strip_suffix("iB")
+        .or_else(|| unit.strip_suffix('B'))
+        .unwrap_or(unit);
+    let multiplier: u64 = match unit.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown size unit in '{}'", value)),
+    };
+    let number: f64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+    if number < 0.0 {
+        return Err(format!("invalid size '{}'", value));
+    }
+    Ok((number * multiplier as f64) as u64)
+}
+
 impl Config {
+    /// Parse the command line (see `parse_with_profile`) and check options
+    /// that depend on each other
+    pub fn load() -> Result<Self> {
+        Self::parse_with_profile()?.checked()
+    }
+
+    /// Parse the command line and profile again for `:reload`, or `None`
+    /// without a profile since the command line can't have changed. Unlike
+    /// at startup, a bad option in the profile is an error instead of exiting.
+    pub fn reload() -> Result<Option<Self>> {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let Some(name) = Self::try_parse_from(&args)?.profile else {
+            return Ok(None);
+        };
+        let config = Self::try_parse_from(with_profile(&name, &args)?).map_err(|e| {
+            // Drop clap's usage and help hints, which don't fit a status bar
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            eyre!("{}", first.trim_start_matches("error: "))
+        })?;
+        config.checked().map(Some)
+    }
+
+    fn checked(self) -> Result<Self> {
+        if let Some(max) = self.max_select.filter(|&max| self.min_select > max) {
+            bail!(
+                "--min-select {} is more than --max-select {}",
+                self.min_select,
+                max
+            );
+        }
+        Ok(self)
+    }
+
+    /// Parse the command line. With `--profile`, the profile's options go
+    /// before the command line's, which therefore win.
+    fn parse_with_profile() -> Result<Self> {
+        let args: Vec<OsString> = std::env::args_os().collect();
+        let config = Self::parse_from(&args);
+        match config.profile {
+            Some(ref name) => Ok(Self::parse_from(with_profile(name, &args)?)),
+            None => Ok(config),
+        }
+    }
+
     pub fn use_absolute_paths(&self) -> bool {
         self.absolute && !self.relative
     }
+
+    /// Whether to draw in color: not with `--no-color`, nor when NO_COLOR is
+    /// set to anything non-empty (https://no-color.org)
+    pub fn color(&self) -> bool {
+        !self.no_color && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+    }
+
+    pub fn glyphs(&self) -> Glyphs {
+        Glyphs::new(
+            &self.cursor_glyph,
+            &self.checked_glyph,
+            &self.unchecked_glyph,
+            &self.marker_glyph,
+        )
+    }
+
+    pub fn output_options(&self) -> OutputOptions {
+        OutputOptions {
+            absolute: self.use_absolute_paths(),
+            dir_slash: self.dir_slash,
+            filter: self.output_filter(),
+            sort: self.sort_output,
+            reverse: self.reverse_output,
+            listing: self.sort_options(),
+            emit_dirs: self.emit_dirs,
+            shell_quote: self.shell_quote,
+            rel_common: self.rel_common,
+            git_relative: self.git_relative,
+            both: self.both,
+            keep_symlinks: self.no_resolve_symlinks,
+            home: self.tilde.then(home_dir).flatten(),
+        }
+    }
+
+    fn output_filter(&self) -> OutputFilter {
+        if self.valid_only {
+            OutputFilter::ValidOnly
+        } else if self.invalid_only {
+            OutputFilter::InvalidOnly
+        } else {
+            OutputFilter::All
+        }
+    }
+
+    pub fn sort_options(&self) -> SortOptions {
+        SortOptions {
+            dirs_first: !self.no_dirs_first,
+            natural: !self.lexical_sort,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn array_names_must_be_shell_variables() {
+        for name in ["files", "_x", "A1_b"] {
+            assert_eq!(parse_variable_name(name).as_deref(), Ok(name));
+        }
+        for name in ["", "1a", "a-b", "a b", "a=b"] {
+            assert!(parse_variable_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn sizes_and_thresholds() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("10K"), Ok(10 << 10));
+        assert_eq!(parse_size("1.5GiB"), Ok(3 << 29));
+        assert_eq!(parse_size(" 2m "), Ok(2 << 20));
+        assert_eq!(parse_size("2MB"), Ok(2 << 20));
+        assert!(parse_size("5T").is_err());
+        assert!(parse_size("M").is_err());
+        assert!(parse_size("-1K").is_err());
+
+        assert_eq!(parse_size_thresholds("1M,100M"), Ok((1 << 20, 100 << 20)));
+        assert_eq!(parse_size_thresholds("1K,1K"), Ok((1 << 10, 1 << 10)));
+        assert!(parse_size_thresholds("100M,1M").is_err());
+        assert!(parse_size_thresholds("1M").is_err());
+    }
 }
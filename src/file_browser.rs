@@ -1,25 +1,56 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Instant, SystemTime};
 
 use color_eyre::Result;
 
+use crate::log::verbose;
+use crate::platform;
+use crate::selection::resolve_invalid;
+
+/// How many directory levels a recursive walk descends at most
+const MAX_RECURSION_DEPTH: usize = 64;
+
 #[derive(Debug, Clone)]
 pub struct FileEntry {
     pub path: PathBuf,
     pub name: String,
     pub is_dir: bool,
     pub is_invalid: bool,
+    /// Modification time, when it could be read
+    pub modified: Option<SystemTime>,
+    /// Size in bytes of a file (not of a directory)
+    pub size: Option<u64>,
+    /// Mode and ownership, on platforms that have them
+    pub permissions: Option<Permissions>,
+}
+
+/// `ls -l` style details of an entry
+#[derive(Debug, Clone)]
+pub struct Permissions {
+    /// Permission bits, including setuid/setgid/sticky
+    pub mode: u32,
+    pub owner: String,
+    pub group: String,
 }
 
 impl FileEntry {
     pub fn from_path(path: PathBuf) -> Self {
         let name = extract_name(&path);
-        let is_dir = path.is_dir();
+        let metadata = fs::metadata(&path).ok();
         Self {
             path,
             name,
-            is_dir,
+            is_dir: metadata.as_ref().is_some_and(fs::Metadata::is_dir),
             is_invalid: false,
+            modified: metadata.as_ref().and_then(|m| m.modified().ok()),
+            size: metadata
+                .as_ref()
+                .filter(|m| !m.is_dir())
+                .map(fs::Metadata::len),
+            permissions: metadata.as_ref().and_then(platform::permissions),
         }
     }
 
@@ -29,16 +60,176 @@ impl FileEntry {
             name: display_name,
             is_dir: false,
             is_invalid: true,
+            modified: None,
+            size: None,
+            permissions: None,
         }
     }
 
-    fn sort_key(&self) -> (u8, u8, String) {
+    fn sort_key(&self, options: SortOptions) -> (u8, u8, SortName) {
         let invalid_order = u8::from(self.is_invalid);
-        let dir_order = u8::from(!self.is_dir);
-        (invalid_order, dir_order, self.name.to_lowercase())
+        let dir_order = if options.dirs_first {
+            u8::from(!self.is_dir)
+        } else {
+            0
+        };
+        let name = SortName {
+            name: self.name.to_lowercase(),
+            natural: options.natural,
+        };
+        (invalid_order, dir_order, name)
+    }
+}
+
+/// Ordering settings for directory listings
+#[derive(Debug, Clone, Copy)]
+pub struct SortOptions {
+    /// Group directories before files
+    pub dirs_first: bool,
+    /// Compare embedded numbers by value, so `file2` sorts before `file10`
+    pub natural: bool,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        Self {
+            dirs_first: true,
+            natural: true,
+        }
+    }
+}
+
+/// Order full paths the way a depth-first walk of the directory listings
+/// would meet them: a directory comes before its contents, and at the first
+/// differing component entries compare as they are sorted in a listing.
+pub fn listing_cmp(a: &Path, b: &Path, options: SortOptions) -> Ordering {
+    let mut parent = PathBuf::new();
+    let mut a_parts = a.components();
+    let mut b_parts = b.components();
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x == y => parent.push(x),
+            (Some(x), Some(y)) => {
+                // Anything with components left below it is a directory
+                let key = |part: Component, rest_empty: bool| {
+                    let is_dir = !rest_empty || parent.join(part).is_dir();
+                    let dir_order = u8::from(options.dirs_first && !is_dir);
+                    let name = SortName {
+                        name: part.as_os_str().to_string_lossy().to_lowercase(),
+                        natural: options.natural,
+                    };
+                    (dir_order, name)
+                };
+                let a_key = key(x, a_parts.as_path().as_os_str().is_empty());
+                let b_key = key(y, b_parts.as_path().as_os_str().is_empty());
+                return a_key.cmp(&b_key).then_with(|| x.cmp(&y));
+            }
+        }
+    }
+}
+
+/// Lowercased entry name ordered either lexically or naturally
+#[derive(Debug, PartialEq, Eq)]
+struct SortName {
+    name: String,
+    natural: bool,
+}
+
+impl Ord for SortName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.natural {
+            natural_cmp(&self.name, &other.name)
+        } else {
+            self.name.cmp(&other.name)
+        }
+    }
+}
+
+impl PartialOrd for SortName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compare strings treating runs of ASCII digits as numbers. Falls back to
+/// lexical order for ties like `01` vs `1` so the ordering stays total.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a_chars = a.chars().peekable();
+    let mut b_chars = b.chars().peekable();
+
+    loop {
+        match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x_digits = take_digits(&mut a_chars);
+                let y_digits = take_digits(&mut b_chars);
+                let x_trimmed = x_digits.trim_start_matches('0');
+                let y_trimmed = y_digits.trim_start_matches('0');
+                let ordering = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a_chars.next();
+                b_chars.next();
+            }
+        }
     }
 }
 
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Match `name` against a glob pattern supporting `*` and `?`.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position of the last `*` and the name index it is currently absorbing up to
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, absorbed)) => {
+                    p = star + 1;
+                    n = absorbed + 1;
+                    backtrack = Some((star, absorbed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 fn extract_name(path: &Path) -> String {
     path.file_name()
         .map(|n| n.to_string_lossy().into_owned())
@@ -52,21 +243,68 @@ pub struct BrowserState {
     pub cursor: usize,
     pub scroll_offset: usize,
     pub show_hidden: bool,
+    /// List only hidden entries, overriding `show_hidden`
+    pub hidden_only: bool,
+    pub sort: SortOptions,
+    /// Extra glob patterns (beyond a leading `.`) that count as hidden
+    hidden_patterns: Vec<String>,
     base_dir: PathBuf,
     invalid_paths: Vec<PathBuf>,
+    /// A file never listed, e.g. the selections file being edited
+    excluded: Option<PathBuf>,
+    /// Visited directories, oldest first, for back/forward navigation
+    history: Vec<PathBuf>,
+    history_pos: usize,
+    /// Last (cursor, scroll_offset) seen in each directory
+    positions: HashMap<PathBuf, (usize, usize)>,
+    /// The directory shown before the current one, for `go_previous`
+    previous_dir: Option<PathBuf>,
+    /// A current directory that was removed while browsing, until reported
+    vanished: Option<PathBuf>,
+    /// Entries read per page in huge directories; `None` reads everything
+    page_size: Option<usize>,
+    /// How many entries of `limit_dir` to read, grown by `load_more`
+    limit: Option<usize>,
+    limit_dir: PathBuf,
+    /// Listed entries of the current directory left unread by the page limit
+    pub unloaded: usize,
+    /// Bumped each time `entries` is read afresh
+    revision: u64,
 }
 
 impl BrowserState {
-    pub fn new(start_dir: PathBuf, show_hidden: bool) -> Result<Self> {
-        let current_dir = start_dir.canonicalize()?;
+    /// Browse from `current_dir`, placing invalid paths relative to `base_dir`
+    /// (both canonical)
+    pub fn new(
+        base_dir: PathBuf,
+        current_dir: PathBuf,
+        show_hidden: bool,
+        sort: SortOptions,
+        hidden_patterns: Vec<String>,
+        page_size: Option<usize>,
+    ) -> Result<Self> {
         let mut state = Self {
-            base_dir: current_dir.clone(),
-            current_dir,
+            base_dir,
+            current_dir: current_dir.clone(),
             entries: Vec::new(),
             cursor: 0,
             scroll_offset: 0,
             show_hidden,
+            hidden_only: false,
+            sort,
+            hidden_patterns,
             invalid_paths: Vec::new(),
+            excluded: None,
+            history: vec![current_dir.clone()],
+            history_pos: 0,
+            positions: HashMap::new(),
+            previous_dir: None,
+            vanished: None,
+            page_size,
+            limit: page_size,
+            limit_dir: PathBuf::new(),
+            unloaded: 0,
+            revision: 0,
         };
         state.refresh()?;
         Ok(state)
@@ -80,21 +318,175 @@ impl BrowserState {
         }
     }
 
+    /// Stop listing an invalid path from the next refresh on
+    pub fn remove_invalid_path(&mut self, path: &Path) {
+        self.invalid_paths.retain(|p| p != path);
+    }
+
+    /// Leave `path` (canonical) out of listings from the next refresh on
+    pub fn exclude(&mut self, path: PathBuf) {
+        self.excluded = Some(path);
+    }
+
+    /// Re-read the current directory, keeping the cursor on the same entry
+    /// if it still exists, or at the same index if it doesn't
     pub fn refresh(&mut self) -> Result<()> {
-        self.entries = self.read_current_directory()?;
+        let previous = self.current_entry().map(|e| e.path.clone());
+        self.load()?;
+        if let Some(index) = previous.and_then(|path| self.position_of(&path)) {
+            self.cursor = index;
+        }
+        Ok(())
+    }
+
+    /// Changes whenever the listing is read afresh, so views narrowing or
+    /// reordering it know to rebuild
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn position_of(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|e| e.path == path)
+    }
+
+    /// Read the current directory into `entries`, bounding the cursor. Used
+    /// directly when navigating, where the old cursor entry is irrelevant.
+    fn load(&mut self) -> Result<()> {
+        let started = Instant::now();
+        if !self.current_dir.is_dir() {
+            self.leave_vanished_dir();
+        }
+        // Every directory starts over at one page
+        if self.limit_dir != self.current_dir {
+            self.limit = self.page_size;
+            self.limit_dir = self.current_dir.clone();
+        }
+        (self.entries, self.unloaded) = self.read_current_directory()?;
+        self.revision += 1;
         self.add_invalid_entries();
-        self.entries.sort_by_key(|e| e.sort_key());
+        let sort = self.sort;
+        self.entries.sort_by_cached_key(|e| e.sort_key(sort));
         self.clamp_cursor();
+        verbose!(
+            2,
+            "refresh: {} entries in {} ({:?})",
+            self.entries.len(),
+            self.current_dir.display(),
+            started.elapsed()
+        );
         Ok(())
     }
 
-    fn read_current_directory(&self) -> Result<Vec<FileEntry>> {
-        let entries = fs::read_dir(&self.current_dir)?
+    /// Move up to the nearest ancestor that still exists after the current
+    /// directory was removed from under the browser
+    fn leave_vanished_dir(&mut self) {
+        let vanished = self.current_dir.clone();
+        while !self.current_dir.is_dir() && self.current_dir.pop() {}
+        verbose!(
+            1,
+            "{} no longer exists, moved to {}",
+            vanished.display(),
+            self.current_dir.display()
+        );
+        self.cursor = 0;
+        self.scroll_offset = 0;
+        self.vanished = Some(vanished);
+    }
+
+    /// The directory the browser had to leave because it was removed, once
+    pub fn take_vanished(&mut self) -> Option<PathBuf> {
+        self.vanished.take()
+    }
+
+    /// Read the listed entries of the current directory, up to the page
+    /// limit, along with how many listed entries were left unread. Entries
+    /// past the limit are only counted, never stat'ed.
+    fn read_current_directory(&self) -> Result<(Vec<FileEntry>, usize)> {
+        let mut entries = Vec::new();
+        let mut unloaded = 0;
+        for entry in fs::read_dir(&self.current_dir)?.filter_map(|e| e.ok()) {
+            let path = entry.path();
+            if !self.is_listed(&extract_name(&path)) || self.excluded.as_ref() == Some(&path) {
+                continue;
+            }
+            if self.limit.is_some_and(|limit| entries.len() >= limit) {
+                unloaded += 1;
+            } else {
+                entries.push(FileEntry::from_path(path));
+            }
+        }
+        Ok((entries, unloaded))
+    }
+
+    /// Read the next page of a directory cut short by the page limit.
+    /// Returns how many more entries were loaded.
+    pub fn load_more(&mut self) -> Result<usize> {
+        let (Some(limit), Some(page_size)) = (self.limit, self.page_size) else {
+            return Ok(0);
+        };
+        let before = self.unloaded;
+        self.limit = Some(limit + page_size);
+        self.refresh()?;
+        Ok(before.saturating_sub(self.unloaded))
+    }
+
+    /// Whether an entry name is hidden by default: dotfiles plus anything
+    /// matching a configured hidden pattern.
+    pub fn is_hidden(&self, name: &str) -> bool {
+        name.starts_with('.') || self.hidden_patterns.iter().any(|p| wildcard_match(p, name))
+    }
+
+    /// Whether an entry name should be listed under the current hidden
+    /// setting. Directory listings and recursive selection both go through
+    /// this so they always agree.
+    pub fn is_visible(&self, name: &str) -> bool {
+        self.show_hidden || !self.is_hidden(name)
+    }
+
+    /// Every file below `dir` that the listing would show, descending only
+    /// into directories it would show. Symlinks to directories are neither
+    /// followed nor returned, so a link back up the tree can't repeat it.
+    pub fn collect_files_recursive(&self, dir: &Path) -> Vec<PathBuf> {
+        self.collect_files_below(dir, 0)
+    }
+
+    fn collect_files_below(&self, dir: &Path, depth: usize) -> Vec<PathBuf> {
+        if depth >= MAX_RECURSION_DEPTH {
+            verbose!(
+                1,
+                "{}: deeper than {} levels, not descending",
+                dir.display(),
+                depth
+            );
+            return Vec::new();
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        entries
             .filter_map(|e| e.ok())
-            .map(|e| FileEntry::from_path(e.path()))
-            .filter(|e| self.show_hidden || !e.name.starts_with('.'))
-            .collect();
-        Ok(entries)
+            .filter(|entry| self.is_visible(&entry.file_name().to_string_lossy()))
+            .flat_map(|entry| {
+                let path = entry.path();
+                match entry.file_type() {
+                    Ok(kind) if kind.is_dir() => self.collect_files_below(&path, depth + 1),
+                    Ok(kind) if kind.is_symlink() && path.is_dir() => Vec::new(),
+                    _ => vec![path],
+                }
+            })
+            .collect()
+    }
+
+    /// Whether an entry of the current directory is listed. The hidden-only
+    /// view only narrows the listing; recursive selection below a listed
+    /// directory still follows `is_visible`.
+    fn is_listed(&self, name: &str) -> bool {
+        if self.hidden_only {
+            self.is_hidden(name)
+        } else {
+            self.is_visible(name)
+        }
     }
 
     fn add_invalid_entries(&mut self) {
@@ -126,23 +518,22 @@ impl BrowserState {
     /// Find where an invalid path should be displayed.
     /// Returns (directory_to_show_in, name_to_display).
     fn find_display_location(&self, path: &Path) -> Option<(PathBuf, String)> {
-        // Make path absolute relative to base_dir
-        let full_path = if path.is_absolute() {
-            path.to_path_buf()
-        } else {
-            self.base_dir.join(path)
+        // Paths that `..` takes above base_dir are walked from the root
+        let full_path = resolve_invalid(path, &self.base_dir);
+        let (mut current, relative) = match full_path.strip_prefix(&self.base_dir) {
+            Ok(relative) => (self.base_dir.clone(), relative),
+            Err(_) => {
+                let root = full_path.ancestors().last()?;
+                (root.to_path_buf(), full_path.strip_prefix(root).ok()?)
+            }
         };
-
-        // Get path relative to base_dir
-        let relative = full_path.strip_prefix(&self.base_dir).ok()?;
         let components: Vec<_> = relative.components().collect();
 
         if components.is_empty() {
             return None;
         }
 
-        // Walk from base_dir, find where the path becomes invalid
-        let mut current = self.base_dir.clone();
+        // Walk down, find where the path becomes invalid
 
         for (i, component) in components.iter().enumerate() {
             let next = current.join(component);
@@ -190,6 +581,10 @@ impl BrowserState {
         if visible_height == 0 {
             return;
         }
+        // Ensure cursor is visible at top, e.g. after a search jump
+        if self.cursor < self.scroll_offset {
+            self.scroll_offset = self.cursor;
+        }
         // Ensure cursor is visible at bottom when scrolling down
         if self.cursor >= self.scroll_offset + visible_height {
             self.scroll_offset = self.cursor - visible_height + 1;
@@ -205,38 +600,576 @@ impl BrowserState {
             return Ok(false);
         }
 
-        self.current_dir = entry.path.clone();
+        let dir = entry.path.clone();
+        self.remember_position();
+        self.current_dir = dir;
         self.cursor = 0;
         self.scroll_offset = 0;
-        self.refresh()?;
+        self.load()?;
+        self.record_visit();
         Ok(true)
     }
 
+    /// Jump straight to `dir`, which must be an existing directory.
+    pub fn goto(&mut self, dir: &Path) -> Result<()> {
+        let dir = dir.canonicalize()?;
+        self.remember_position();
+        self.current_dir = dir;
+        self.cursor = 0;
+        self.scroll_offset = 0;
+        self.load()?;
+        self.record_visit();
+        Ok(())
+    }
+
     pub fn go_parent(&mut self) -> Result<bool> {
-        let Some(parent) = self.current_dir.parent() else {
+        let Some(parent) = self.current_dir.parent().map(Path::to_path_buf) else {
             return Ok(false);
         };
 
         let old_dir = self.current_dir.clone();
-        self.current_dir = parent.to_path_buf();
-        self.refresh()?;
+        self.remember_position();
+        self.current_dir = parent;
+        self.load()?;
+        self.record_visit();
 
-        self.cursor = self
-            .entries
-            .iter()
-            .position(|e| e.path == old_dir)
-            .unwrap_or(0);
+        self.cursor = self.position_of(&old_dir).unwrap_or(0);
         self.scroll_offset = self.cursor; // Position cursor at top
 
         Ok(true)
     }
 
+    /// Return to the previously visited directory, like a browser's back button.
+    pub fn go_back(&mut self) -> Result<bool> {
+        if self.history_pos == 0 {
+            return Ok(false);
+        }
+        self.history_pos -= 1;
+        self.revisit()?;
+        Ok(true)
+    }
+
+    /// Undo a `go_back`.
+    pub fn go_forward(&mut self) -> Result<bool> {
+        if self.history_pos + 1 >= self.history.len() {
+            return Ok(false);
+        }
+        self.history_pos += 1;
+        self.revisit()?;
+        Ok(true)
+    }
+
+    /// Switch to the directory shown before this one, like `cd -`; pressed
+    /// again it switches back.
+    pub fn go_previous(&mut self) -> Result<bool> {
+        let Some(dir) = self.previous_dir.clone().filter(|dir| dir.is_dir()) else {
+            return Ok(false);
+        };
+        self.remember_position();
+        self.current_dir = dir;
+        self.restore_position();
+        self.load()?;
+        self.record_visit();
+        Ok(true)
+    }
+
+    /// Show the directory at `history_pos`, restoring its remembered cursor.
+    fn revisit(&mut self) -> Result<()> {
+        self.remember_position();
+        self.current_dir = self.history[self.history_pos].clone();
+        self.restore_position();
+        self.load()
+    }
+
+    /// Note the cursor in the directory being left, and that it was left.
+    /// Every navigation calls this before changing `current_dir`.
+    fn remember_position(&mut self) {
+        self.positions
+            .insert(self.current_dir.clone(), (self.cursor, self.scroll_offset));
+        self.previous_dir = Some(self.current_dir.clone());
+    }
+
+    fn restore_position(&mut self) {
+        let (cursor, scroll_offset) = self
+            .positions
+            .get(&self.current_dir)
+            .copied()
+            .unwrap_or_default();
+        self.cursor = cursor;
+        self.scroll_offset = scroll_offset;
+    }
+
+    /// Push `current_dir` onto the history, dropping any forward entries.
+    fn record_visit(&mut self) {
+        if self.history.get(self.history_pos) == Some(&self.current_dir) {
+            return;
+        }
+        self.history.truncate(self.history_pos + 1);
+        self.history.push(self.current_dir.clone());
+        self.history_pos = self.history.len() - 1;
+    }
+
+    /// Show or hide hidden entries, leaving the hidden-only view if active
     pub fn toggle_hidden(&mut self) -> Result<()> {
-        self.show_hidden = !self.show_hidden;
+        if self.hidden_only {
+            self.hidden_only = false;
+        } else {
+            self.show_hidden = !self.show_hidden;
+        }
         self.refresh()
     }
 
+    /// Change how entries are sorted and which count as hidden
+    pub fn set_listing(
+        &mut self,
+        show_hidden: bool,
+        sort: SortOptions,
+        hidden_patterns: Vec<String>,
+    ) -> Result<()> {
+        self.show_hidden = show_hidden;
+        self.sort = sort;
+        self.hidden_patterns = hidden_patterns;
+        self.refresh()
+    }
+
+    pub fn toggle_hidden_only(&mut self) -> Result<()> {
+        self.hidden_only = !self.hidden_only;
+        self.refresh()
+    }
+
+    /// Narrow the listing down until the next refresh, keeping the cursor
+    /// on its entry if that stays
+    pub fn retain_entries(&mut self, keep: impl FnMut(&FileEntry) -> bool) {
+        let previous = self.current_entry().map(|e| e.path.clone());
+        self.entries.retain(keep);
+        self.clamp_cursor();
+        if let Some(path) = previous {
+            self.move_to(&path);
+        }
+    }
+
+    /// Move entries for which `first` holds to the top, each group in
+    /// listing order. The cursor stays on its entry.
+    pub fn float_to_top(&mut self, mut first: impl FnMut(&FileEntry) -> bool) {
+        let previous = self.current_entry().map(|e| e.path.clone());
+        let sort = self.sort;
+        self.entries
+            .sort_by_cached_key(|e| (!first(e), e.sort_key(sort)));
+        if let Some(path) = previous {
+            self.move_to(&path);
+        }
+    }
+
+    /// Put the cursor on the entry for `path`, if it is listed
+    pub fn move_to(&mut self, path: &Path) {
+        if let Some(index) = self.position_of(path) {
+            self.cursor = index;
+        }
+    }
+
     pub fn current_entry(&self) -> Option<&FileEntry> {
         self.entries.get(self.cursor)
     }
+
+    /// Number of directories and files in the listing as shown, leaving out
+    /// invalid entries
+    pub fn entry_counts(&self) -> (usize, usize) {
+        let valid = self.entries.iter().filter(|e| !e.is_invalid);
+        let dirs = valid.clone().filter(|e| e.is_dir).count();
+        let files = valid.filter(|e| !e.is_dir).count();
+        (dirs, files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn browser(dir: &Path, show_hidden: bool, hidden_patterns: &[&str]) -> BrowserState {
+        let sort = SortOptions {
+            dirs_first: true,
+            natural: true,
+        };
+        let patterns = hidden_patterns.iter().map(|p| p.to_string()).collect();
+        BrowserState::new(
+            dir.to_owned(),
+            dir.to_owned(),
+            show_hidden,
+            sort,
+            patterns,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn listed_names(browser: &BrowserState) -> Vec<&str> {
+        browser.entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    fn relative_files(browser: &BrowserState, dir: &Path) -> Vec<String> {
+        let mut files: Vec<String> = browser
+            .collect_files_recursive(dir)
+            .iter()
+            .map(|path| {
+                let rel = path.strip_prefix(dir).unwrap();
+                let parts: Vec<_> = rel.iter().map(|part| part.to_string_lossy()).collect();
+                parts.join("/")
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn hidden_names() {
+        let dir = TempDir::new("hidden-names");
+        let browser = browser(dir.path(), false, &["*.bak"]);
+        for name in [".", "..", ".config", ".a.b", "notes.bak"] {
+            assert!(browser.is_hidden(name), "{} should be hidden", name);
+            assert!(!browser.is_visible(name), "{} should not be visible", name);
+        }
+        for name in ["file.", "config", "a.bak.txt", "x.config"] {
+            assert!(!browser.is_hidden(name), "{} should not be hidden", name);
+            assert!(browser.is_visible(name), "{} should be visible", name);
+        }
+    }
+
+    #[test]
+    fn shown_hidden_names_are_visible() {
+        let dir = TempDir::new("shown-hidden");
+        let browser = browser(dir.path(), true, &[]);
+        assert!(browser.is_hidden(".config"));
+        assert!(browser.is_visible(".config"));
+    }
+
+    #[test]
+    fn listing_and_recursion_agree_on_hidden() {
+        let dir = TempDir::new("listing-recursion");
+        let all = [
+            ".config/inner",
+            ".dot",
+            "file.",
+            "keep",
+            "sub/.dot",
+            "sub/file.",
+            "sub/x.bak",
+        ];
+        for file in all {
+            dir.file(file);
+        }
+
+        let hiding = browser(dir.path(), false, &["*.bak"]);
+        assert_eq!(listed_names(&hiding), ["sub", "file.", "keep"]);
+        assert_eq!(
+            relative_files(&hiding, dir.path()),
+            ["file.", "keep", "sub/file."]
+        );
+
+        let showing = browser(dir.path(), true, &["*.bak"]);
+        assert_eq!(
+            listed_names(&showing),
+            [".config", "sub", ".dot", "file.", "keep"]
+        );
+        // Showing hidden entries reveals the pattern-hidden ones too
+        assert_eq!(relative_files(&showing, dir.path()), all);
+    }
+
+    #[test]
+    fn directories_first_or_mixed_with_invalid_last() {
+        let dir = TempDir::new("dirs-first");
+        for file in ["b", "d/x", "a", "c/x"] {
+            dir.file(file);
+        }
+        let mut browser = browser(dir.path(), false, &[]);
+        browser.add_invalid_paths(vec![PathBuf::from("0-missing")]);
+        browser.refresh().unwrap();
+        assert_eq!(listed_names(&browser), ["c", "d", "a", "b", "0-missing"]);
+
+        let mixed = SortOptions {
+            dirs_first: false,
+            natural: true,
+        };
+        browser.set_listing(false, mixed, Vec::new()).unwrap();
+        assert_eq!(listed_names(&browser), ["a", "b", "c", "d", "0-missing"]);
+    }
+
+    #[test]
+    fn natural_order_compares_numbers() {
+        let mut names = ["img20", "img10", "img2", "img1", "img02", "img"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["img", "img1", "img02", "img2", "img10", "img20"]);
+        assert_eq!(natural_cmp("a10b2", "a10b10"), Ordering::Less);
+        assert_eq!(natural_cmp("b1", "a2"), Ordering::Greater);
+    }
+
+    #[test]
+    fn lexical_order_when_natural_is_off() {
+        let dir = TempDir::new("lexical-order");
+        for file in ["img10", "img2", "img1"] {
+            dir.file(file);
+        }
+        let mut browser = browser(dir.path(), false, &[]);
+        assert_eq!(listed_names(&browser), ["img1", "img2", "img10"]);
+
+        let lexical = SortOptions {
+            dirs_first: true,
+            natural: false,
+        };
+        browser.set_listing(false, lexical, Vec::new()).unwrap();
+        assert_eq!(listed_names(&browser), ["img1", "img10", "img2"]);
+    }
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(wildcard_match("*~", "notes.txt~"));
+        assert!(wildcard_match("*.b?k", "x.bak"));
+        assert!(wildcard_match("a*b*c", "aXbYbc"));
+        assert!(wildcard_match(".DS_Store", ".DS_Store"));
+        assert!(!wildcard_match("*~", "notes.txt"));
+        assert!(!wildcard_match("?", ""));
+        assert!(!wildcard_match("a*b", "ab.c"));
+    }
+
+    #[test]
+    fn custom_hidden_patterns_are_hidden_and_revealed() {
+        let dir = TempDir::new("hidden-patterns");
+        for file in ["notes.txt~", "notes.txt", "Store", "sub/.DS_Store"] {
+            dir.file(file);
+        }
+        let mut browser = browser(dir.path(), false, &["*~", "Store"]);
+        assert_eq!(listed_names(&browser), ["sub", "notes.txt"]);
+        assert_eq!(relative_files(&browser, dir.path()), ["notes.txt"]);
+
+        let sort = SortOptions {
+            dirs_first: true,
+            natural: true,
+        };
+        browser
+            .set_listing(true, sort, vec!["*~".into(), "Store".into()])
+            .unwrap();
+        assert_eq!(
+            listed_names(&browser),
+            ["sub", "notes.txt", "notes.txt~", "Store"]
+        );
+    }
+
+    #[test]
+    fn back_and_forward_history() {
+        let dir = TempDir::new("history");
+        for file in ["a/x", "a/y", "b/z"] {
+            dir.file(file);
+        }
+        let root = dir.path();
+        let mut browser = browser(root, false, &[]);
+        let at = |browser: &BrowserState| {
+            let name = browser
+                .current_entry()
+                .map_or("", |e| e.name.as_str())
+                .to_owned();
+            (browser.current_dir.clone(), name)
+        };
+
+        browser.move_to(&root.join("b"));
+        assert!(browser.enter_directory().unwrap());
+        assert!(browser.go_parent().unwrap());
+        browser.move_to(&root.join("a"));
+        assert!(browser.enter_directory().unwrap());
+        browser.move_to(&root.join("a/y"));
+
+        assert!(browser.go_back().unwrap());
+        assert_eq!(at(&browser), (root.to_owned(), "a".to_owned()));
+        assert!(browser.go_back().unwrap());
+        assert_eq!(at(&browser), (root.join("b"), "z".to_owned()));
+        // The cursor is remembered per directory, not per history entry
+        assert!(browser.go_back().unwrap());
+        assert_eq!(at(&browser), (root.to_owned(), "a".to_owned()));
+        assert!(!browser.go_back().unwrap());
+
+        assert!(browser.go_forward().unwrap());
+        assert!(browser.go_forward().unwrap());
+        assert!(browser.go_forward().unwrap());
+        assert_eq!(at(&browser), (root.join("a"), "y".to_owned()));
+        assert!(!browser.go_forward().unwrap());
+
+        // Navigating anywhere after going back drops the forward history
+        assert!(browser.go_back().unwrap());
+        browser.goto(&root.join("b")).unwrap();
+        assert!(!browser.go_forward().unwrap());
+        assert!(browser.go_back().unwrap());
+        assert_eq!(browser.current_dir, root);
+    }
+
+    #[test]
+    fn invalid_paths_show_where_they_resolve() {
+        let dir = TempDir::new("invalid-location");
+        dir.file("base/a/x");
+        dir.file("sibling/y");
+        let base = dir.path().join("base");
+        let sort = SortOptions {
+            dirs_first: true,
+            natural: true,
+        };
+        let mut browser =
+            BrowserState::new(base.clone(), base.clone(), false, sort, Vec::new(), None).unwrap();
+        let invalid = ["../sibling/gone", "a/../a/missing", "../nowhere/file"];
+        browser.add_invalid_paths(invalid.map(PathBuf::from).to_vec());
+
+        browser.goto(&base.join("a")).unwrap();
+        assert_eq!(listed_names(&browser), ["x", "missing"]);
+        browser.goto(&dir.path().join("sibling")).unwrap();
+        assert_eq!(listed_names(&browser), ["y", "gone"]);
+        let nowhere = Path::new("nowhere").join("file").display().to_string();
+        browser.goto(dir.path()).unwrap();
+        assert_eq!(
+            listed_names(&browser),
+            ["base", "sibling", nowhere.as_str()]
+        );
+    }
+
+    #[test]
+    fn refresh_keeps_the_cursor_on_its_file() {
+        let dir = TempDir::new("refresh-cursor");
+        for file in ["b", "c", "d"] {
+            dir.file(file);
+        }
+        let mut browser = browser(dir.path(), false, &[]);
+        browser.move_to(&dir.path().join("c"));
+        assert_eq!(browser.cursor, 1);
+
+        dir.file("a");
+        browser.refresh().unwrap();
+        assert_eq!(browser.current_entry().unwrap().name, "c");
+        assert_eq!(browser.cursor, 2);
+
+        // A vanished cursor file leaves the cursor at the same index
+        fs::remove_file(dir.path().join("c")).unwrap();
+        browser.refresh().unwrap();
+        assert_eq!(browser.current_entry().unwrap().name, "d");
+    }
+
+    #[test]
+    fn vanished_directory_climbs_to_an_existing_ancestor() {
+        let dir = TempDir::new("vanished-dir");
+        dir.file("keep/gone/deeper/file");
+        let mut browser = browser(dir.path(), false, &[]);
+        browser.goto(&dir.path().join("keep/gone/deeper")).unwrap();
+
+        fs::remove_dir_all(dir.path().join("keep/gone")).unwrap();
+        browser.refresh().unwrap();
+        assert_eq!(browser.current_dir, dir.path().join("keep"));
+        assert!(browser.entries.is_empty());
+        assert_eq!(
+            browser.take_vanished(),
+            Some(dir.path().join("keep/gone/deeper"))
+        );
+        assert_eq!(browser.take_vanished(), None);
+    }
+
+    #[test]
+    fn hidden_listing_states() {
+        let dir = TempDir::new("hidden-only");
+        for file in [".rc", ".config/x", "plain", "sub/y"] {
+            dir.file(file);
+        }
+        let mut browser = browser(dir.path(), false, &[]);
+        assert_eq!(listed_names(&browser), ["sub", "plain"]);
+        browser.toggle_hidden().unwrap();
+        assert_eq!(listed_names(&browser), [".config", "sub", ".rc", "plain"]);
+        browser.toggle_hidden_only().unwrap();
+        assert_eq!(listed_names(&browser), [".config", ".rc"]);
+
+        // Toggling hidden entries leaves the hidden-only view for the previous one
+        browser.toggle_hidden().unwrap();
+        assert_eq!(listed_names(&browser), [".config", "sub", ".rc", "plain"]);
+    }
+
+    #[test]
+    fn previous_directory_toggles() {
+        let dir = TempDir::new("previous-dir");
+        for file in ["a/x", "a/y", "b/z"] {
+            dir.file(file);
+        }
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+        let mut browser = browser(dir.path(), false, &[]);
+        assert!(!browser.go_previous().unwrap());
+        browser.goto(&a).unwrap();
+        browser.move_to(&a.join("y"));
+        browser.goto(&b).unwrap();
+
+        assert!(browser.go_previous().unwrap());
+        assert_eq!(browser.current_dir, a);
+        assert_eq!(browser.current_entry().unwrap().name, "y");
+        assert!(browser.go_previous().unwrap());
+        assert_eq!(browser.current_dir, b);
+        assert!(browser.go_previous().unwrap());
+        assert_eq!(browser.current_dir, a);
+    }
+
+    #[test]
+    fn listing_order_of_nested_paths() {
+        let dir = TempDir::new("listing-cmp");
+        for file in ["b/x", "a10", "a2", "B1", "z/deep/f"] {
+            dir.file(file);
+        }
+        let mut paths: Vec<PathBuf> = ["z/deep/f", "a10", "b/x", "b", "a2", "B1", "gone/x"]
+            .iter()
+            .map(|rel| dir.path().join(rel))
+            .collect();
+        let sorted = |paths: &mut Vec<PathBuf>, options| {
+            paths.sort_by(|a, b| listing_cmp(a, b, options));
+            let names: Vec<String> = paths
+                .iter()
+                .map(|path| {
+                    let rel = path.strip_prefix(dir.path()).unwrap();
+                    let parts: Vec<_> = rel.iter().map(|part| part.to_string_lossy()).collect();
+                    parts.join("/")
+                })
+                .collect();
+            names.join(" ")
+        };
+        // Parents precede their contents, and a missing path with contents
+        // counts as a directory
+        let dirs_first = SortOptions {
+            dirs_first: true,
+            natural: true,
+        };
+        assert_eq!(
+            sorted(&mut paths, dirs_first),
+            "b b/x gone/x z/deep/f a2 a10 B1"
+        );
+        let mixed = SortOptions {
+            dirs_first: false,
+            natural: false,
+        };
+        assert_eq!(sorted(&mut paths, mixed), "a10 a2 b b/x B1 gone/x z/deep/f");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn recursion_skips_directory_symlinks() {
+        let dir = TempDir::new("recursion-symlinks");
+        let big = dir.file("sub/big");
+        std::os::unix::fs::symlink(".", dir.path().join("sub/loop")).unwrap();
+        std::os::unix::fs::symlink(&big, dir.path().join("sub/link-to-file")).unwrap();
+        let browser = browser(dir.path(), false, &[]);
+        assert_eq!(
+            relative_files(&browser, dir.path()),
+            ["sub/big", "sub/link-to-file"]
+        );
+    }
+
+    #[test]
+    fn recursion_stops_at_the_depth_cap() {
+        let dir = TempDir::new("recursion-depth");
+        let shallow = ["d"; 10].join("/");
+        let deep = ["d"; MAX_RECURSION_DEPTH + 1].join("/");
+        dir.file(&format!("{}/shallow", shallow));
+        dir.file(&format!("{}/deep", deep));
+        let browser = browser(dir.path(), false, &[]);
+        assert_eq!(
+            relative_files(&browser, dir.path()),
+            [format!("{}/shallow", shallow)]
+        );
+    }
 }
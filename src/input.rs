@@ -1,19 +1,162 @@
-use std::io::{self, BufRead, IsTerminal};
+use std::io::{self, BufRead, IsTerminal, Read};
 use std::path::PathBuf;
+use std::time::Duration;
 
-pub fn read_stdin_paths() -> Vec<PathBuf> {
+use crate::log::verbose;
+use crate::platform;
+
+/// Paths piped on stdin, one per line. With `idle_timeout`, reading stops
+/// once stdin stays silent that long, so a pipe that never closes doesn't
+/// keep the TUI from starting.
+pub fn read_stdin_paths(idle_timeout: Option<Duration>) -> Vec<PathBuf> {
     let stdin = io::stdin();
 
     if stdin.is_terminal() {
         return Vec::new();
     }
 
-    stdin
-        .lock()
-        .lines()
-        .map_while(Result::ok)
+    let lines: Vec<String> = match idle_timeout {
+        Some(timeout) => read_until_idle(timeout),
+        None => stdin.lock().lines().map_while(Result::ok).collect(),
+    };
+    let line_count = lines.len();
+
+    let paths: Vec<PathBuf> = lines
+        .into_iter()
         .map(|line| line.trim().to_owned())
         .filter(|line| !line.is_empty())
         .map(PathBuf::from)
-        .collect()
+        .collect();
+
+    verbose!(
+        1,
+        "stdin: read {} lines, {} paths after dropping blanks",
+        line_count,
+        paths.len()
+    );
+    paths
+}
+
+/// Expand a leading `~` and `$VAR`/`${VAR}` in a path that reached us
+/// unexpanded, e.g. from a file. Unset variables and non-UTF-8 paths are
+/// left as written.
+pub fn expand_env(path: PathBuf) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path;
+    };
+    if !text.starts_with('~') && !text.contains('$') {
+        return path;
+    }
+
+    let mut expanded = String::new();
+    let mut rest = text;
+    if let Some(after) = text.strip_prefix('~') {
+        if after.is_empty() || after.starts_with(std::path::is_separator) {
+            if let Some(home) = std::env::home_dir().as_deref().and_then(|h| h.to_str()) {
+                expanded.push_str(home);
+                rest = after;
+            }
+        }
+    }
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match std::env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[dollar..dollar + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+/// Lines read until stdin closes or goes quiet for `timeout`. A line still
+/// being written when the timeout hits is dropped rather than taken as a
+/// truncated path.
+fn read_until_idle(timeout: Duration) -> Vec<String> {
+    let mut data = Vec::new();
+    // Larger than stdin's buffer, so reads go straight to the descriptor
+    // and never wait for more than what poll reported
+    let mut chunk = vec![0; 64 * 1024];
+    let mut stdin = io::stdin().lock();
+    let mut timed_out = false;
+    loop {
+        if !platform::stdin_ready(timeout) {
+            timed_out = true;
+            break;
+        }
+        match stdin.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => data.extend_from_slice(&chunk[..n]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+
+    let mut text = String::from_utf8_lossy(&data).into_owned();
+    if timed_out {
+        verbose!(
+            1,
+            "stdin: silent for {:?}, continuing without waiting for EOF",
+            timeout
+        );
+        if !text.is_empty() && !text.ends_with('\n') {
+            let complete = text.rfind('\n').map_or(0, |i| i + 1);
+            verbose!(1, "stdin: dropping incomplete line {:?}", &text[complete..]);
+            text.truncate(complete);
+        }
+    }
+    text.lines().map(str::to_owned).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(path: &str) -> PathBuf {
+        expand_env(PathBuf::from(path))
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn home_and_variables_expand() {
+        let home = std::env::home_dir().unwrap();
+        assert_eq!(expand("~/foo"), home.join("foo"));
+        assert_eq!(expand("~"), home);
+        assert_eq!(expand("~user/foo"), PathBuf::from("~user/foo"));
+        assert_eq!(expand("a/~/b"), PathBuf::from("a/~/b"));
+
+        let var = std::env::var("HOME").unwrap();
+        assert_eq!(expand("$HOME/foo"), PathBuf::from(format!("{}/foo", var)));
+        assert_eq!(expand("x${HOME}y"), PathBuf::from(format!("x{}y", var)));
+    }
+
+    #[test]
+    fn unknown_variables_stay_literal() {
+        let paths = [
+            "$5 price.txt",
+            "a$",
+            "$FS_UNSET_VAR/x",
+            "${unclosed",
+            "${}",
+            "plain",
+        ];
+        for path in paths {
+            assert_eq!(expand(path), PathBuf::from(path));
+        }
+    }
 }
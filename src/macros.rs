@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use crossterm::event::{KeyCode, KeyEvent};
+
+/// Longest macro kept; later keys are not recorded
+const MAX_MACRO_KEYS: usize = 1000;
+
+/// Vim-style key macros: `Q<register>` starts recording, `Q` stops, and
+/// `@<register>` replays the recorded keys.
+#[derive(Debug, Default)]
+pub struct Macros {
+    registers: HashMap<char, Vec<KeyEvent>>,
+    recording: Option<(char, Vec<KeyEvent>)>,
+    awaiting: Option<Awaiting>,
+    replaying: bool,
+}
+
+/// What the next key names a register for
+#[derive(Debug, Clone, Copy)]
+enum Awaiting {
+    Record,
+    Replay,
+}
+
+/// How a key press relates to macros
+pub enum MacroStep {
+    /// The key started or stopped recording, or named a register
+    Consumed,
+    /// Keys to feed back through the key handler
+    Replay(Vec<KeyEvent>),
+    /// An ordinary key, to be handled (and recorded) as usual
+    Pass,
+}
+
+impl Macros {
+    /// Check `key` for macro commands. `Q` and `@` only count in normal
+    /// mode, so prompts can still receive them as text.
+    pub fn intercept(&mut self, key: KeyEvent, normal_mode: bool) -> MacroStep {
+        if let Some(awaiting) = self.awaiting.take() {
+            let KeyCode::Char(register) = key.code else {
+                return MacroStep::Consumed;
+            };
+            if !register.is_ascii_alphanumeric() {
+                return MacroStep::Consumed;
+            }
+            return match awaiting {
+                Awaiting::Record => {
+                    self.recording = Some((register, Vec::new()));
+                    MacroStep::Consumed
+                }
+                // A macro replaying another could recurse forever
+                Awaiting::Replay if self.replaying => MacroStep::Consumed,
+                Awaiting::Replay => self
+                    .registers
+                    .get(&register)
+                    .cloned()
+                    .map_or(MacroStep::Consumed, MacroStep::Replay),
+            };
+        }
+
+        if !normal_mode {
+            return MacroStep::Pass;
+        }
+        match key.code {
+            KeyCode::Char('Q') => {
+                match self.recording.take() {
+                    Some((register, keys)) => {
+                        self.registers.insert(register, keys);
+                    }
+                    None => self.awaiting = Some(Awaiting::Record),
+                }
+                MacroStep::Consumed
+            }
+            KeyCode::Char('@') => {
+                self.awaiting = Some(Awaiting::Replay);
+                MacroStep::Consumed
+            }
+            _ => MacroStep::Pass,
+        }
+    }
+
+    /// Add a handled key to the macro being recorded, if any
+    pub fn record(&mut self, key: KeyEvent) {
+        if self.replaying {
+            return;
+        }
+        if let Some((_, keys)) = self.recording.as_mut() {
+            if keys.len() < MAX_MACRO_KEYS {
+                keys.push(key);
+            }
+        }
+    }
+
+    /// Register being recorded into
+    pub fn recording(&self) -> Option<char> {
+        self.recording.as_ref().map(|(register, _)| *register)
+    }
+
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+}
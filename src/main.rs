@@ -1,32 +1,56 @@
 mod app;
+mod checksum;
+mod command;
 mod config;
 mod file_browser;
 mod input;
+mod log;
+mod macros;
+mod platform;
 mod selection;
+#[cfg(test)]
+mod test_util;
 mod ui;
 
 use std::fs::File;
-use std::io::{self, BufRead, IsTerminal, Write};
-use std::os::unix::io::AsRawFd;
+use std::io::{self, IsTerminal, Write};
+use std::panic;
 use std::path::Path;
+use std::time::Duration;
 
-use clap::Parser;
+use color_eyre::eyre::{eyre, WrapErr};
 use color_eyre::Result;
 use crossterm::{
+    clipboard::CopyToClipboard,
+    cursor,
     event::{self, Event},
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, is_raw_mode_enabled, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use ratatui::prelude::*;
 
 use app::{App, AppAction};
 use config::Config;
 
+/// How often the event loop wakes without input to update timers
+const TICK: Duration = Duration::from_millis(250);
+
+/// Per-project default selections, looked for in the start directory
+const PROJECT_FILE: &str = ".fileselect";
+
+/// Exit status after cancelling with --output-on-cancel and printing anyway
+const CANCELLED_EXIT: i32 = 2;
+
 fn main() -> Result<()> {
     color_eyre::install()?;
 
-    let stdin_paths = input::read_stdin_paths();
-    let config = Config::parse();
+    let config = Config::load()?;
+    log::set_verbosity(config.verbose);
+
+    let stdin_paths = input::read_stdin_paths(config.stdin_timeout);
 
     let file_paths = config
         .selections_file
@@ -35,65 +59,240 @@ fn main() -> Result<()> {
         .transpose()?
         .unwrap_or_default();
 
-    let pre_selected = [config.files.clone(), stdin_paths, file_paths].concat();
     let start_dir = std::env::current_dir()?;
+    let project_paths = if config.no_project {
+        Vec::new()
+    } else {
+        let base_dir = start_dir.canonicalize()?;
+        read_project_file(&app::browse_dir(&base_dir, &config)?, &base_dir)?
+    };
+
+    let mut pre_selected = [config.files.clone(), stdin_paths, file_paths].concat();
+    if config.expand_env {
+        pre_selected = pre_selected.into_iter().map(input::expand_env).collect();
+    }
+    pre_selected.extend(project_paths);
 
-    let mut app = App::new(
-        start_dir,
-        config.show_hidden,
-        config.use_absolute_paths(),
-        pre_selected,
-        config.selections_file.clone(),
-    )?;
+    let mut app = App::new(start_dir, pre_selected, &config)?;
 
-    let confirmed = run_tui(&mut app)?;
+    if config.strict && app.selection.invalid_count() > 0 {
+        for path in app.get_invalid_list() {
+            eprintln!("file-list: no such file: {}", path.display());
+        }
+        std::process::exit(1);
+    }
+
+    let confirmed = run_tui(&mut app, &config)?;
 
     if confirmed {
-        let output = app.get_output();
-        if let Some(ref path) = config.selections_file {
-            write_selections_file(path, &output)?;
+        write_output(&app, &config)?;
+        if config.summary {
+            eprintln!("{}", summary(&app));
+        }
+    } else if config.output_on_cancel && app.selection.count() > 0 {
+        write_output(&app, &config)?;
+        std::process::exit(CANCELLED_EXIT);
+    } else if config.count_zero_on_cancel {
+        println!("0");
+    }
+
+    Ok(())
+}
+
+/// Write the confirmed selection to the selections file, or stdout if none.
+fn write_output(app: &App, config: &Config) -> Result<()> {
+    let output = app.get_output();
+    if let Some(ref path) = config.selections_file {
+        write_selections_file(path, &output)?;
+    } else {
+        let mut stdout = io::stdout().lock();
+        if let Some(ref header) = config.header {
+            writeln!(stdout, "{}", header)?;
+        }
+        if let Some(ref name) = config.bash_array {
+            writeln!(stdout, "{}", array_assignment(name, &output))?;
+        } else if config.count {
+            writeln!(stdout, "{}", output.len())?;
+        } else if config.checksums {
+            checksum::write_manifest(&mut stdout, &app.get_located_output())?;
         } else {
             for path in output {
-                println!("{}", path);
+                writeln!(stdout, "{}", path)?;
             }
         }
+        stdout.flush()?;
     }
-
     Ok(())
 }
 
-fn run_tui(app: &mut App) -> Result<bool> {
-    let mut tty = File::options().read(true).write(true).open("/dev/tty")?;
+/// `name=( 'a' 'b' )`, for --bash-array
+fn array_assignment(name: &str, paths: &[String]) -> String {
+    let mut assignment = format!("{}=(", name);
+    for path in paths {
+        assignment.push(' ');
+        assignment.push_str(&selection::shell_quote(path));
+    }
+    assignment + " )"
+}
 
-    if !io::stdin().is_terminal() {
-        unsafe {
-            libc::dup2(tty.as_raw_fd(), 0);
-        }
+/// One-line description of what was confirmed, for --summary
+fn summary(app: &App) -> String {
+    let total = app.selection.count();
+    let invalid = app.selection.invalid_count();
+    let dirs = app.selection.dir_count();
+    let files = total - invalid - dirs;
+    format!(
+        "Selected {} {} ({} {}, {} {}, {} invalid)",
+        total,
+        plural(total, "path", "paths"),
+        files,
+        plural(files, "file", "files"),
+        dirs,
+        plural(dirs, "directory", "directories"),
+        invalid
+    )
+}
+
+pub(crate) fn plural<'a>(count: usize, one: &'a str, many: &'a str) -> &'a str {
+    if count == 1 {
+        one
+    } else {
+        many
     }
+}
 
-    enable_raw_mode()?;
-    execute!(tty, EnterAlternateScreen)?;
+fn run_tui(app: &mut App, config: &Config) -> Result<bool> {
+    let tty = open_terminal(config)?;
+    platform::redirect_stdin(&tty);
+    platform::catch_interrupt();
 
+    let _guard = TerminalGuard::enter(tty.try_clone()?)?;
     let backend = CrosstermBackend::new(tty);
     let mut terminal = Terminal::new(backend)?;
 
-    let result = event_loop(&mut terminal, app);
+    event_loop(&mut terminal, app, config)
+}
+
+/// Raw mode and the alternate screen for as long as it lives. Restoring the
+/// terminal on drop covers errors, interrupts and panics (which unwind
+/// through it) as well as a normal exit.
+struct TerminalGuard {
+    tty: File,
+}
+
+impl TerminalGuard {
+    fn enter(mut tty: File) -> Result<Self> {
+        enable_raw_mode()?;
+        let guard = Self {
+            tty: tty.try_clone()?,
+        };
+
+        // The panic report is printed before unwinding reaches the guard, so
+        // restore first or it would land on the alternate screen and vanish
+        let panic_tty = tty.try_clone()?;
+        let report = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal(&panic_tty);
+            report(info);
+        }));
+
+        execute!(tty, EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal(&self.tty);
+    }
+}
+
+/// Leave raw mode and the alternate screen, unless that was already done:
+/// leaving the alternate screen again would move the cursor back up over
+/// whatever was printed since.
+fn restore_terminal(mut tty: &File) {
+    if !is_raw_mode_enabled().unwrap_or(true) {
+        return;
+    }
+    // Nothing sensible is left to do if restoring fails
+    let _ = disable_raw_mode();
+    let _ = execute!(tty, LeaveAlternateScreen, cursor::Show);
+}
+
+/// Open the terminal the TUI draws on. Without `--tty`, environments lacking
+/// the default terminal fall back to stderr when it is a terminal; stdout stays free
+/// for the output either way.
+fn open_terminal(config: &Config) -> Result<File> {
+    let open = |path: &Path| File::options().read(true).write(true).open(path);
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-    terminal.show_cursor()?;
+    if let Some(ref path) = config.tty {
+        return open(path).wrap_err_with(|| format!("cannot open terminal {}", path.display()));
+    }
 
-    result
+    match open(Path::new(platform::DEFAULT_TTY)) {
+        Ok(tty) => Ok(tty),
+        Err(err) if io::stderr().is_terminal() => {
+            log::verbose!(
+                1,
+                "cannot open {} ({}), using stderr",
+                platform::DEFAULT_TTY,
+                err
+            );
+            Ok(platform::stderr_terminal()?)
+        }
+        Err(err) => Err(eyre!(
+            "no usable terminal: cannot open {} ({}) and stderr is not a terminal; \
+             pass one with --tty",
+            platform::DEFAULT_TTY,
+            err
+        )),
+    }
 }
 
-fn event_loop(terminal: &mut Terminal<CrosstermBackend<File>>, app: &mut App) -> Result<bool> {
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<File>>,
+    app: &mut App,
+    config: &Config,
+) -> Result<bool> {
+    let mut batches = 0;
+
     loop {
         terminal.draw(|f| ui::render(f, app))?;
 
+        // An interrupt cancels like q; Ctrl-C itself arrives as a key
+        if app.timed_out() || platform::interrupted() {
+            return Ok(false);
+        }
+
+        // Keep a large addition moving between redraws instead of waiting
+        let timeout = if app.add_progress().is_some() {
+            app.process_pending_add();
+            Duration::ZERO
+        } else {
+            TICK
+        };
+        if !event::poll(timeout)? {
+            app.check_current_dir()?;
+            continue;
+        }
+
         if let Event::Key(key) = event::read()? {
             match app.handle_key(key)? {
                 AppAction::Continue => {}
                 AppAction::Quit => return Ok(false),
+                AppAction::Confirm if config.loop_mode => {
+                    // Separate stdout batches with a blank line; a selections
+                    // file is simply rewritten with the latest batch
+                    if batches > 0 && config.selections_file.is_none() {
+                        println!();
+                    }
+                    write_output(app, config)?;
+                    batches += 1;
+                    if config.loop_clear {
+                        app.clear_selection();
+                    }
+                    app.status_message = Some(format!("Sent batch {}", batches));
+                }
                 AppAction::Confirm => return Ok(true),
                 AppAction::Save => {
                     if let Some(path) = app.selections_file() {
@@ -101,20 +300,53 @@ fn event_loop(terminal: &mut Terminal<CrosstermBackend<File>>, app: &mut App) ->
                         write_selections_file(path, &output)?;
                     }
                 }
+                // OSC 52, so it also works over SSH; terminals without
+                // support ignore it
+                AppAction::Copy(text) => execute!(
+                    terminal.backend_mut(),
+                    CopyToClipboard::to_clipboard_from(text)
+                )?,
             }
         }
     }
 }
 
+/// Paths listed in the project file of the directory browsing starts in, if
+/// it has one. They are relative to that directory, so they are rebased onto
+/// `base_dir`, where relative pre-selections are resolved from; that leaves
+/// them as written when browsing starts in `base_dir` itself.
+fn read_project_file(dir: &Path, base_dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let path = dir.join(PROJECT_FILE);
+    let paths = read_selections_file(&path)?;
+    if !paths.is_empty() {
+        log::verbose!(1, "{}: {} project selections", path.display(), paths.len());
+    }
+    let prefix = dir.strip_prefix(base_dir).unwrap_or(dir);
+    Ok(paths.into_iter().map(|p| prefix.join(p)).collect())
+}
+
 fn read_selections_file(path: &Path) -> Result<Vec<std::path::PathBuf>> {
     if !path.exists() {
         return Ok(Vec::new());
     }
 
-    let file = File::open(path)?;
-    let paths = io::BufReader::new(file)
+    let content = std::fs::read_to_string(path)?;
+
+    // JSON is detected by its first byte; anything that then fails to parse
+    // is treated as plain lines, since a filename may start with `[` too
+    if content.trim_start().starts_with(['[', '{']) {
+        match parse_json_paths(&content) {
+            Some(paths) => return Ok(paths),
+            None => log::verbose!(
+                1,
+                "{}: not a JSON path list, reading as lines",
+                path.display()
+            ),
+        }
+    }
+
+    let paths = content
         .lines()
-        .map_while(Result::ok)
         .map(|line| line.trim().to_owned())
         .filter(|line| !line.is_empty())
         .map(std::path::PathBuf::from)
@@ -123,10 +355,117 @@ fn read_selections_file(path: &Path) -> Result<Vec<std::path::PathBuf>> {
     Ok(paths)
 }
 
+/// Parse either a JSON array of path strings or an object whose `paths`
+/// field holds such an array.
+fn parse_json_paths(content: &str) -> Option<Vec<std::path::PathBuf>> {
+    let value: serde_json::Value = serde_json::from_str(content).ok()?;
+    let array = match value {
+        serde_json::Value::Array(array) => array,
+        serde_json::Value::Object(mut object) => match object.remove("paths")? {
+            serde_json::Value::Array(array) => array,
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    array
+        .into_iter()
+        .map(|item| item.as_str().map(std::path::PathBuf::from))
+        .collect()
+}
+
 fn write_selections_file(path: &Path, paths: &[String]) -> Result<()> {
     let mut file = File::create(path)?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        writeln!(file, "{}", serde_json::to_string_pretty(paths)?)?;
+        return Ok(());
+    }
     for p in paths {
         writeln!(file, "{}", p)?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::path::PathBuf;
+
+    fn read(dir: &TempDir, name: &str, content: &str) -> Vec<PathBuf> {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        read_selections_file(&path).unwrap()
+    }
+
+    #[test]
+    fn selections_file_formats() {
+        let dir = TempDir::new("selections-file");
+        let expected = [PathBuf::from("a b"), PathBuf::from("c")];
+        assert_eq!(read(&dir, "lines", "a b\n\n  c  \n"), expected);
+        assert_eq!(read(&dir, "array", r#" ["a b", "c"]"#), expected);
+        assert_eq!(
+            read(&dir, "object", r#"{"paths": ["a b", "c"], "n": 2}"#),
+            expected
+        );
+        assert_eq!(
+            read(&dir, "bad", "[a b\nc"),
+            [PathBuf::from("[a b"), PathBuf::from("c")]
+        );
+        assert!(read_selections_file(&dir.path().join("missing"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn json_selections_file_round_trips() {
+        let dir = TempDir::new("selections-round-trip");
+        let path = dir.path().join("out.json");
+        let paths = ["./a".to_owned(), "./b \"c\"".to_owned()];
+        write_selections_file(&path, &paths).unwrap();
+        assert_eq!(
+            read_selections_file(&path).unwrap(),
+            paths.map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn bash_array_quotes_each_path() {
+        let paths = ["./a b".to_owned(), "./it's".to_owned()];
+        assert_eq!(
+            array_assignment("files", &paths),
+            r"files=( './a b' './it'\''s' )"
+        );
+        assert_eq!(array_assignment("none", &[]), "none=( )");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bash_array_evaluates_to_the_paths() {
+        let paths = [
+            "./a b".to_owned(),
+            "./it's".to_owned(),
+            "./$x `y`\n*".to_owned(),
+        ];
+        for shell in ["bash", "zsh"] {
+            let script = format!(
+                "{}; printf '%s\\0' \"${{#f[@]}}\" \"${{f[@]}}\"",
+                array_assignment("f", &paths)
+            );
+            let Ok(out) = std::process::Command::new(shell)
+                .arg("-c")
+                .arg(script)
+                .output()
+            else {
+                continue;
+            };
+            let expected = format!("3\0{}\0", paths.join("\0"));
+            assert_eq!(
+                String::from_utf8(out.stdout).unwrap(),
+                expected,
+                "{}",
+                shell
+            );
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! The only OS-specific code: finding the terminal the TUI draws on,
+//! making sure key presses are read from it even when stdin is piped,
+//! waiting on piped input, noticing interrupts, reading file modes and
+//! owners, and locating the configuration directory.
+
+use std::fs::{File, Metadata};
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::file_browser::Permissions;
+
+/// Terminal opened when `--tty` isn't given
+#[cfg(unix)]
+pub const DEFAULT_TTY: &str = "/dev/tty";
+#[cfg(windows)]
+pub const DEFAULT_TTY: &str = "CONOUT$";
+
+/// Where per-user configuration such as profiles is kept
+#[cfg(unix)]
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(std::env::home_dir()?.join(".config")))
+}
+
+#[cfg(windows)]
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("APPDATA").map(PathBuf::from)
+}
+
+/// A handle on stderr usable as the TUI's output
+#[cfg(unix)]
+pub fn stderr_terminal() -> io::Result<File> {
+    use std::os::unix::io::AsFd;
+
+    Ok(File::from(io::stderr().as_fd().try_clone_to_owned()?))
+}
+
+#[cfg(windows)]
+pub fn stderr_terminal() -> io::Result<File> {
+    use std::os::windows::io::AsHandle;
+
+    Ok(File::from(io::stderr().as_handle().try_clone_to_owned()?))
+}
+
+/// Wait up to `timeout` for stdin to have data or reach its end
+#[cfg(unix)]
+pub fn stdin_ready(timeout: Duration) -> bool {
+    let mut fd = libc::pollfd {
+        fd: 0,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = timeout.as_millis().min(i32::MAX as u128) as i32;
+    // SAFETY: polls exactly the one descriptor passed
+    unsafe { libc::poll(&mut fd, 1, millis) > 0 }
+}
+
+/// Windows can't poll a pipe this way, so reads simply block as without a
+/// timeout
+#[cfg(windows)]
+pub fn stdin_ready(_timeout: Duration) -> bool {
+    true
+}
+
+/// crossterm reads keys from stdin on Unix, so piped input is replaced by
+/// the terminal once the pre-selections have been read from it.
+#[cfg(unix)]
+pub fn redirect_stdin(tty: &File) {
+    use std::io::IsTerminal;
+    use std::os::unix::io::AsRawFd;
+
+    if !io::stdin().is_terminal() {
+        unsafe {
+            libc::dup2(tty.as_raw_fd(), 0);
+        }
+    }
+}
+
+/// crossterm reads keys from the console input buffer on Windows, which is
+/// independent of a redirected stdin.
+#[cfg(windows)]
+pub fn redirect_stdin(_tty: &File) {}
+
+#[cfg(unix)]
+static INTERRUPTED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Turn SIGINT (e.g. `kill -INT` from elsewhere; Ctrl-C in raw mode is a key
+/// press) into a flag the event loop checks, so the terminal is restored
+/// instead of the process dying in raw mode.
+#[cfg(unix)]
+pub fn catch_interrupt() {
+    extern "C" fn on_interrupt(_signal: libc::c_int) {
+        INTERRUPTED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is signal safe
+    unsafe {
+        let handler: extern "C" fn(libc::c_int) = on_interrupt;
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Raw mode turns Ctrl-C into a key event on Windows too, and no other
+/// interrupt needs catching.
+#[cfg(windows)]
+pub fn catch_interrupt() {}
+
+/// Whether SIGINT was received since `catch_interrupt`
+#[cfg(unix)]
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+#[cfg(windows)]
+pub fn interrupted() -> bool {
+    false
+}
+
+/// Mode and owner/group names of a file
+#[cfg(unix)]
+pub fn permissions(metadata: &Metadata) -> Option<Permissions> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some(Permissions {
+        mode: metadata.mode() & 0o7777,
+        owner: user_name(metadata.uid()),
+        group: group_name(metadata.gid()),
+    })
+}
+
+/// Windows has no Unix modes, so the permissions column stays empty
+#[cfg(windows)]
+pub fn permissions(_metadata: &Metadata) -> Option<Permissions> {
+    None
+}
+
+/// Name of the user with `uid`, or the number when it has none
+#[cfg(unix)]
+fn user_name(uid: u32) -> String {
+    thread_local! {
+        static USERS: NameCache = NameCache::default();
+    }
+    USERS.with(|cache| {
+        cache.lookup(uid, || {
+            // SAFETY: the record is only read before the next lookup
+            let entry = unsafe { libc::getpwuid(uid) };
+            (!entry.is_null()).then(|| unsafe { (*entry).pw_name.cast_const() })
+        })
+    })
+}
+
+/// Name of the group with `gid`, or the number when it has none
+#[cfg(unix)]
+fn group_name(gid: u32) -> String {
+    thread_local! {
+        static GROUPS: NameCache = NameCache::default();
+    }
+    GROUPS.with(|cache| {
+        cache.lookup(gid, || {
+            // SAFETY: the record is only read before the next lookup
+            let entry = unsafe { libc::getgrgid(gid) };
+            (!entry.is_null()).then(|| unsafe { (*entry).gr_name.cast_const() })
+        })
+    })
+}
+
+/// Names already looked up, since every entry of a listing asks
+#[cfg(unix)]
+#[derive(Default)]
+struct NameCache(std::cell::RefCell<std::collections::HashMap<u32, String>>);
+
+#[cfg(unix)]
+impl NameCache {
+    fn lookup(&self, id: u32, find: impl FnOnce() -> Option<*const libc::c_char>) -> String {
+        self.0
+            .borrow_mut()
+            .entry(id)
+            .or_insert_with(|| match find() {
+                // SAFETY: names in passwd and group records are C strings
+                Some(name) if !name.is_null() => unsafe { std::ffi::CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned(),
+                _ => id.to_string(),
+            })
+            .clone()
+    }
+}
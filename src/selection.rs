@@ -1,12 +1,112 @@
-use std::collections::HashSet;
-use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::file_browser::{listing_cmp, SortOptions};
+use crate::log::verbose;
+
+/// Settings controlling how selections are written out
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Emit absolute paths instead of paths relative to the base directory
+    pub absolute: bool,
+    /// Append a trailing slash to directory paths
+    pub dir_slash: bool,
+    /// Which of the valid and invalid selections to emit
+    pub filter: OutputFilter,
+    /// Key the emitted paths are ordered by
+    pub sort: OutputSort,
+    /// Emit in descending instead of ascending order
+    pub reverse: bool,
+    /// Directory listing order, for `OutputSort::Disk`
+    pub listing: SortOptions,
+    /// Emit the distinct directories containing selections instead of the selections
+    pub emit_dirs: bool,
+    /// Wrap each path in single quotes so the output can be `eval`ed
+    pub shell_quote: bool,
+    /// Make relative paths relative to the deepest directory containing
+    /// every selection instead of the base directory
+    pub rel_common: bool,
+    /// Follow each relative path with a tab and its absolute form
+    pub both: bool,
+    /// Emit valid paths as they were selected, through any symlinks,
+    /// instead of canonicalized
+    pub keep_symlinks: bool,
+    /// Home directory to abbreviate as `~` in absolute paths
+    pub home: Option<PathBuf>,
+    /// Emit valid paths inside a git repository relative to its root
+    pub git_relative: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum OutputSort {
+    /// Alphabetical by emitted path
+    #[default]
+    Name,
+    /// By modification time; invalid paths go last
+    Mtime,
+    /// By file size; invalid paths go last
+    Size,
+    /// As a depth-first walk of the Files pane listings would meet them
+    Disk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFilter {
+    #[default]
+    All,
+    ValidOnly,
+    InvalidOnly,
+}
+
+impl OutputFilter {
+    fn includes_valid(self) -> bool {
+        self != Self::InvalidOnly
+    }
+
+    fn includes_invalid(self) -> bool {
+        self != Self::ValidOnly
+    }
+}
+
+/// What `add_paths` did with each path it was given
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddCounts {
+    pub added: usize,
+    /// Already selected, possibly through another form of the same path
+    pub duplicates: usize,
+    /// Turned away by the selection limit
+    pub refused: usize,
+}
+
+/// Outcome of inserting one path
+enum Insert {
+    Added,
+    Duplicate,
+    Refused,
+}
 
 #[derive(Debug, Default)]
 pub struct SelectionState {
     /// Valid paths (canonicalized, files exist)
     valid: HashSet<PathBuf>,
-    /// Invalid paths (files don't exist, stored as provided)
+    /// Invalid paths (files don't exist, stored as provided after resolving
+    /// `.` and `..` lexically, see `normalize_path`)
     invalid: HashSet<PathBuf>,
+    /// Free-form notes keyed by the stored (canonical or as-provided) path;
+    /// dropped when the path is deselected
+    notes: HashMap<PathBuf, String>,
+    /// Absolute form a valid path was selected through, by canonical path,
+    /// where the two differ (symlinks along the way)
+    originals: HashMap<PathBuf, PathBuf>,
+    /// Most paths that may be selected at once (`--max-select`)
+    limit: Option<usize>,
+    /// Whether an addition was refused for `limit` since last asked
+    limit_hit: bool,
+    /// Bumped by every call that may change which paths are selected
+    revision: u64,
 }
 
 impl SelectionState {
@@ -14,39 +114,153 @@ impl SelectionState {
         Self::default()
     }
 
-    pub fn add_paths(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+    /// Select the given paths, counting which were added, already selected
+    /// or refused for the limit
+    pub fn add_paths(&mut self, paths: impl IntoIterator<Item = PathBuf>) -> AddCounts {
+        self.revision += 1;
+        // Large batches mostly share a handful of directories; resolving each
+        // directory once avoids walking every path component per file
+        let mut dirs = HashMap::new();
+        let mut counts = AddCounts::default();
         for path in paths {
-            match path.canonicalize() {
+            let outcome = match canonicalize_cached(&path, &mut dirs) {
                 Ok(canonical) => {
-                    self.valid.insert(canonical);
+                    verbose!(3, "select: {} -> {}", path.display(), canonical.display());
+                    self.insert_valid(canonical, &path)
                 }
-                Err(_) => {
-                    self.invalid.insert(path);
+                Err(err) => {
+                    verbose!(1, "select: {} is invalid ({})", path.display(), err);
+                    self.insert_invalid(normalize_path(&path))
                 }
+            };
+            match outcome {
+                Insert::Added => counts.added += 1,
+                Insert::Duplicate => counts.duplicates += 1,
+                Insert::Refused => counts.refused += 1,
+            }
+        }
+        counts
+    }
+
+    /// Select a valid path, remembering the form it was selected through.
+    /// Forms with `..` aren't kept, since lexically resolving them could
+    /// lead somewhere else after a symlink.
+    fn insert_valid(&mut self, canonical: PathBuf, path: &Path) -> Insert {
+        if self.valid.contains(&canonical) {
+            return Insert::Duplicate;
+        }
+        if self.refuse_for_limit() {
+            return Insert::Refused;
+        }
+        let has_parent_dir = path.components().any(|c| c == Component::ParentDir);
+        if let (false, Ok(original)) = (has_parent_dir, std::path::absolute(path)) {
+            let original = normalize_path(&original);
+            if original != canonical {
+                self.originals.insert(canonical.clone(), original);
             }
         }
+        self.valid.insert(canonical);
+        Insert::Added
+    }
+
+    fn insert_invalid(&mut self, path: PathBuf) -> Insert {
+        if self.invalid.contains(&path) {
+            return Insert::Duplicate;
+        }
+        if self.refuse_for_limit() {
+            return Insert::Refused;
+        }
+        self.invalid.insert(path);
+        Insert::Added
+    }
+
+    /// Whether a new path would go over the limit, noting it if so
+    fn refuse_for_limit(&mut self) -> bool {
+        let full = self.limit.is_some_and(|limit| self.count() >= limit);
+        self.limit_hit |= full;
+        full
+    }
+
+    /// Changes whenever the selected paths may have, so views built from
+    /// them know to rebuild
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn set_limit(&mut self, limit: Option<usize>) {
+        self.limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Whether an addition was cut short by the limit since the last call
+    pub fn take_limit_hit(&mut self) -> bool {
+        std::mem::take(&mut self.limit_hit)
+    }
+
+    fn remove_valid(&mut self, canonical: &Path) -> bool {
+        self.notes.remove(canonical);
+        self.originals.remove(canonical);
+        self.valid.remove(canonical)
+    }
+
+    pub fn invalid_count(&self) -> usize {
+        self.invalid.len()
+    }
+
+    /// Number of selected directories; the other valid selections are files
+    pub fn dir_count(&self) -> usize {
+        self.valid.iter().filter(|p| p.is_dir()).count()
     }
 
     pub fn remove_paths(&mut self, paths: &[PathBuf]) {
+        self.revision += 1;
         for path in paths {
             if let Ok(canonical) = path.canonicalize() {
-                self.valid.remove(&canonical);
+                self.remove_valid(&canonical);
             }
         }
     }
 
+    /// Remove an invalid path as it was provided. Returns whether it was
+    /// selected.
+    pub fn remove_invalid(&mut self, path: &Path) -> bool {
+        self.revision += 1;
+        let path = normalize_path(path);
+        self.notes.remove(&path);
+        self.invalid.remove(&path)
+    }
+
+    /// Deselect the invalid path that `location` names, whichever form
+    /// (relative to `base_dir` or absolute) it was selected in
+    pub fn remove_invalid_at(&mut self, location: &Path, base_dir: &Path) -> bool {
+        let location = normalize_path(location);
+        let stored = self
+            .invalid
+            .iter()
+            .find(|path| resolve_invalid(path, base_dir) == location)
+            .cloned();
+        stored.is_some_and(|path| self.remove_invalid(&path))
+    }
+
     pub fn toggle(&mut self, path: &Path) {
+        self.revision += 1;
         if let Ok(canonical) = path.canonicalize() {
-            if !self.valid.remove(&canonical) {
-                self.valid.insert(canonical);
+            if !self.remove_valid(&canonical) {
+                self.insert_valid(canonical, path);
             }
         }
     }
 
     pub fn toggle_invalid(&mut self, path: &Path) {
-        let path = path.to_path_buf();
-        if !self.invalid.remove(&path) {
-            self.invalid.insert(path);
+        self.revision += 1;
+        let path = normalize_path(path);
+        if self.invalid.remove(&path) {
+            self.notes.remove(&path);
+        } else {
+            self.insert_invalid(path);
         }
     }
 
@@ -60,6 +274,70 @@ impl SelectionState {
         self.invalid.contains(path)
     }
 
+    /// Whether anything strictly inside `dir` is selected
+    pub fn any_selected_below(&self, dir: &Path, base_dir: &Path) -> bool {
+        let Ok(dir) = dir.canonicalize() else {
+            return false;
+        };
+        self.valid.iter().any(|p| p.starts_with(&dir) && *p != dir)
+            || self
+                .invalid
+                .iter()
+                .any(|p| resolve_invalid(p, base_dir).starts_with(&dir))
+    }
+
+    /// Deselect everything strictly inside `dir`, placing invalid paths by
+    /// resolving them against `base_dir`. Returns how many were removed.
+    pub fn remove_below(&mut self, dir: &Path, base_dir: &Path) -> usize {
+        self.revision += 1;
+        let Ok(dir) = dir.canonicalize() else {
+            return 0;
+        };
+        let valid: Vec<PathBuf> = self
+            .valid
+            .iter()
+            .filter(|p| p.starts_with(&dir) && **p != dir)
+            .cloned()
+            .collect();
+        let invalid: Vec<PathBuf> = self
+            .invalid
+            .iter()
+            .filter(|p| resolve_invalid(p, base_dir).starts_with(&dir))
+            .cloned()
+            .collect();
+
+        for path in &valid {
+            self.remove_valid(path);
+        }
+        for path in &invalid {
+            self.notes.remove(path);
+            self.invalid.remove(path);
+        }
+        valid.len() + invalid.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.revision += 1;
+        self.valid.clear();
+        self.invalid.clear();
+        self.notes.clear();
+        self.originals.clear();
+    }
+
+    /// Attach a note to a selected path as stored (see `iter_valid` and
+    /// `iter_invalid`). An empty note removes it.
+    pub fn set_note(&mut self, path: &Path, note: String) {
+        if note.is_empty() {
+            self.notes.remove(path);
+        } else if self.valid.contains(path) || self.invalid.contains(path) {
+            self.notes.insert(path.to_path_buf(), note);
+        }
+    }
+
+    pub fn note(&self, path: &Path) -> Option<&str> {
+        self.notes.get(path).map(String::as_str)
+    }
+
     pub fn count(&self) -> usize {
         self.valid.len() + self.invalid.len()
     }
@@ -72,41 +350,731 @@ impl SelectionState {
         self.invalid.iter()
     }
 
-    pub fn to_output(&self, use_absolute: bool, base_dir: &Path) -> Vec<String> {
-        let mut paths: Vec<String> = self
+    pub fn to_output(&self, options: &OutputOptions, base_dir: &Path) -> Vec<String> {
+        self.to_located_output(options, base_dir)
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect()
+    }
+
+    /// `to_output` lines, each with the absolute location its path stands for
+    pub fn to_located_output(
+        &self,
+        options: &OutputOptions,
+        base_dir: &Path,
+    ) -> Vec<(String, PathBuf)> {
+        let quote = |path: String| {
+            let path = match options.home {
+                Some(ref home) => collapse_home(path, home),
+                None => path,
+            };
+            if options.shell_quote {
+                shell_quote(&path)
+            } else {
+                path
+            }
+        };
+        self.sorted_output(options, base_dir)
+            .into_iter()
+            .map(|(path, location)| {
+                if !options.both {
+                    return (quote(path), location);
+                }
+                let mut absolute = location.to_string_lossy().into_owned();
+                if path.ends_with('/') && !absolute.ends_with('/') {
+                    absolute.push('/');
+                }
+                (format!("{}\t{}", quote(path), quote(absolute)), location)
+            })
+            .collect()
+    }
+
+    /// Emitted paths in output order, each with the absolute location it
+    /// stands for (resolved against `base_dir` for invalid paths)
+    fn sorted_output(&self, options: &OutputOptions, base_dir: &Path) -> Vec<(String, PathBuf)> {
+        let mut valid: Vec<&PathBuf> = self
             .valid
             .iter()
-            .map(|p| format_path(p, base_dir, use_absolute))
-            .chain(
-                self.invalid
-                    .iter()
-                    .map(|p| format_invalid_path(p, base_dir, use_absolute)),
-            )
+            .filter(|_| options.filter.includes_valid())
+            .collect();
+        let mut invalid: Vec<&PathBuf> = self
+            .invalid
+            .iter()
+            .filter(|_| options.filter.includes_invalid())
+            .collect();
+
+        let dirs: Vec<PathBuf>;
+        if options.emit_dirs {
+            dirs = containing_dirs(&valid, &invalid, base_dir);
+            valid = dirs.iter().collect();
+            invalid.clear();
+        }
+
+        // Without a common directory besides the root, paths stay absolute
+        let common = if options.rel_common {
+            common_ancestor(&valid, &invalid, base_dir)
+        } else {
+            Some(base_dir.to_path_buf())
+        };
+        let absolute = options.absolute || common.is_none();
+        let anchor = common.as_deref().unwrap_or(base_dir);
+
+        let mut valid: Vec<(String, &PathBuf)> = valid
+            .into_iter()
+            .map(|p| {
+                let emitted = match self.originals.get(p) {
+                    Some(original) if options.keep_symlinks => original,
+                    _ => p,
+                };
+                let formatted = match options.git_relative.then(|| git_relative(emitted)) {
+                    Some(Some(relative)) => relative,
+                    _ => format_path(emitted, anchor, absolute),
+                };
+                if options.dir_slash && p.is_dir() && !formatted.ends_with('/') {
+                    (formatted + "/", emitted)
+                } else {
+                    (formatted, emitted)
+                }
+            })
+            .collect();
+        let mut invalid: Vec<(String, PathBuf)> = invalid
+            .into_iter()
+            .map(|p| {
+                let resolved = resolve_invalid(p, base_dir);
+                if options.rel_common {
+                    (format_path(&resolved, anchor, absolute), resolved)
+                } else {
+                    (format_invalid_path(p, base_dir, options.absolute), resolved)
+                }
+            })
             .collect();
-        paths.sort();
-        paths
+
+        if matches!(options.sort, OutputSort::Name | OutputSort::Disk) {
+            // Valid and invalid paths interleave, as both have a name and a location
+            let mut paths: Vec<(String, PathBuf)> = valid
+                .into_iter()
+                .map(|(s, p)| (s, p.clone()))
+                .chain(invalid)
+                .collect();
+            if options.sort == OutputSort::Disk {
+                paths.sort_by(|(_, a), (_, b)| listing_cmp(a, b, options.listing));
+            } else {
+                paths.sort();
+            }
+            if options.reverse {
+                paths.reverse();
+            }
+            return paths;
+        }
+
+        match options.sort {
+            OutputSort::Mtime => valid.sort_by_cached_key(|(s, p)| (modified_time(p), s.clone())),
+            OutputSort::Size => valid.sort_by_cached_key(|(s, p)| (file_size(p), s.clone())),
+            OutputSort::Name | OutputSort::Disk => {}
+        }
+        if options.reverse {
+            valid.reverse();
+        }
+
+        // Invalid paths have no metadata, so they always trail the valid ones
+        invalid.sort();
+        valid
+            .into_iter()
+            .map(|(s, p)| (s, p.clone()))
+            .chain(invalid)
+            .collect()
+    }
+}
+
+/// Same result as `path.canonicalize()`, but reuses canonical parent
+/// directories from `dirs`. Symlinks are resolved in full as usual, and so
+/// are paths ending in `/` or `/.`, which only resolve for a directory.
+fn canonicalize_cached(path: &Path, dirs: &mut HashMap<PathBuf, PathBuf>) -> io::Result<PathBuf> {
+    // `Path` drops a trailing `/` or `/.` from the file name, so look at the raw text
+    let raw = path.as_os_str().as_encoded_bytes();
+    let last = raw
+        .rsplit(|&b| std::path::is_separator(char::from(b)))
+        .next();
+    let names_dir = matches!(last, Some(b"" | b"."));
+    let (Some(parent), Some(name), false) = (path.parent(), path.file_name(), names_dir) else {
+        return path.canonicalize();
+    };
+    let parent = if parent.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        parent
+    };
+    // Checked without any trailing slash, which would make lstat follow the link
+    if fs::symlink_metadata(parent.join(name))?
+        .file_type()
+        .is_symlink()
+    {
+        return path.canonicalize();
+    }
+    if let Some(dir) = dirs.get(parent) {
+        return Ok(dir.join(name));
+    }
+    let dir = parent.canonicalize()?;
+    let canonical = dir.join(name);
+    dirs.insert(parent.to_path_buf(), dir);
+    Ok(canonical)
+}
+
+/// Distinct parent directories of the given selections. Invalid paths are
+/// resolved against `base_dir` first, matching how they are output.
+fn containing_dirs(valid: &[&PathBuf], invalid: &[&PathBuf], base_dir: &Path) -> Vec<PathBuf> {
+    let dirs: HashSet<PathBuf> = valid
+        .iter()
+        .map(|p| p.to_path_buf())
+        .chain(invalid.iter().map(|p| resolve_invalid(p, base_dir)))
+        .filter_map(|p| p.parent().map(Path::to_path_buf))
+        .collect();
+    dirs.into_iter().collect()
+}
+
+/// Deepest directory containing all the given selections, unless that is
+/// the filesystem root
+fn common_ancestor(valid: &[&PathBuf], invalid: &[&PathBuf], base_dir: &Path) -> Option<PathBuf> {
+    let mut parents = valid
+        .iter()
+        .map(|p| p.to_path_buf())
+        .chain(invalid.iter().map(|p| resolve_invalid(p, base_dir)))
+        .filter_map(|p| p.parent().map(Path::to_path_buf));
+
+    let mut common = parents.next()?;
+    for parent in parents {
+        while !parent.starts_with(&common) {
+            common.pop();
+        }
     }
+    common.parent().is_some().then_some(common)
+}
+
+/// Quote for POSIX shells: everything inside single quotes is literal, and
+/// an embedded `'` becomes `'\''` (close, escaped quote, reopen).
+pub fn shell_quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
 }
 
 fn format_path(path: &Path, base_dir: &Path, use_absolute: bool) -> String {
     if use_absolute {
         path.to_string_lossy().into_owned()
     } else {
-        path.strip_prefix(base_dir)
-            .map(|rel| format!("./{}", rel.display()))
-            .unwrap_or_else(|_| path.to_string_lossy().into_owned())
+        match path.strip_prefix(base_dir) {
+            Ok(rel) if rel.as_os_str().is_empty() => ".".to_owned(),
+            Ok(rel) => format!("./{}", rel.display()),
+            Err(_) => path.to_string_lossy().into_owned(),
+        }
     }
 }
 
-fn format_invalid_path(path: &Path, base_dir: &Path, use_absolute: bool) -> String {
-    if use_absolute {
-        base_dir.join(path).to_string_lossy().into_owned()
+/// `path` relative to the root of the git repository containing it (the
+/// nearest ancestor, or itself, holding a `.git`), as git prints paths
+fn git_relative(path: &Path) -> Option<String> {
+    let root = path.ancestors().find(|dir| dir.join(".git").exists())?;
+    let relative = path.strip_prefix(root).ok()?;
+    if relative.as_os_str().is_empty() {
+        Some(".".to_owned())
     } else {
-        let s = path.to_string_lossy();
-        if s.starts_with("./") || s.starts_with('/') {
-            s.into_owned()
-        } else {
-            format!("./{}", s)
+        Some(relative.to_string_lossy().into_owned())
+    }
+}
+
+/// Replace a leading `home` in an absolute `path` with `~`, keeping any
+/// trailing slash
+pub fn collapse_home(path: String, home: &Path) -> String {
+    let trailing_slash = if path.ends_with('/') && path.len() > 1 {
+        "/"
+    } else {
+        ""
+    };
+    match Path::new(&path).strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => format!("~{}", trailing_slash),
+        Ok(rest) => format!("~/{}{}", rest.display(), trailing_slash),
+        Err(_) => path,
+    }
+}
+
+/// Relative invalid paths are shown `./`-prefixed, unless `..` takes them
+/// above `base_dir`, where stripping the prefix would be meaningless and
+/// the resolved absolute path is shown instead.
+pub fn format_invalid_path(path: &Path, base_dir: &Path, use_absolute: bool) -> String {
+    let resolved = resolve_invalid(path, base_dir);
+    if use_absolute || path.is_absolute() {
+        return resolved.to_string_lossy().into_owned();
+    }
+    match resolved.strip_prefix(base_dir) {
+        Ok(rel) => format!("./{}", rel.display()),
+        Err(_) => resolved.to_string_lossy().into_owned(),
+    }
+}
+
+/// Absolute location of an invalid path, with relative paths taken from
+/// `base_dir`
+pub fn resolve_invalid(path: &Path, base_dir: &Path) -> PathBuf {
+    normalize_path(&base_dir.join(path))
+}
+
+/// Resolve `.` and `..` components without touching the filesystem, so
+/// `a/../b` becomes `b`. Leading `..` of a relative path are kept. Symlinks
+/// can't matter here: this is only used for paths that don't exist.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn canonicalize_cached_matches_canonicalize() {
+        let dir = TempDir::new("canonicalize-cached");
+        let root = dir.path();
+        dir.file("d/file.txt");
+        dir.file("d/sub/other");
+        let mut inputs: Vec<PathBuf> = [
+            "d/file.txt",
+            "d/sub/other",
+            "d",
+            "d/",
+            "d/.",
+            "d/file.txt/",
+            "d/file.txt/.",
+            "d/sub/../file.txt",
+            "d/sub/..",
+            "d/missing",
+            "d/missing/x",
+        ]
+        .iter()
+        .map(|rel| root.join(rel))
+        .collect();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+            symlink(root.join("d/file.txt"), root.join("file-link")).unwrap();
+            symlink(root.join("d"), root.join("dir-link")).unwrap();
+            symlink(root.join("missing"), root.join("dangling")).unwrap();
+            for rel in [
+                "file-link",
+                "file-link/",
+                "dir-link/file.txt",
+                "dir-link/",
+                "dangling",
+            ] {
+                inputs.push(root.join(rel));
+            }
+        }
+
+        let mut dirs = HashMap::new();
+        // The second round answers from the cache filled by the first
+        for _ in 0..2 {
+            for path in &inputs {
+                assert_eq!(
+                    canonicalize_cached(path, &mut dirs).ok(),
+                    path.canonicalize().ok(),
+                    "{}",
+                    path.display()
+                );
+            }
+        }
+    }
+
+    /// A selection of the `rel` paths under `dir`, existing or not
+    fn select(dir: &TempDir, rel: &[&str]) -> SelectionState {
+        let mut selection = SelectionState::new();
+        selection.add_paths(rel.iter().map(|rel| dir.path().join(rel)));
+        selection
+    }
+
+    #[test]
+    fn dir_slash_marks_directories() {
+        let dir = TempDir::new("dir-slash");
+        dir.file("d/inner");
+        dir.file("f");
+        let selection = select(&dir, &["d", "f"]);
+        let mut options = OutputOptions::default();
+        assert_eq!(selection.to_output(&options, dir.path()), ["./d", "./f"]);
+        options.dir_slash = true;
+        assert_eq!(selection.to_output(&options, dir.path()), ["./d/", "./f"]);
+        options.absolute = true;
+        assert_eq!(
+            selection.to_output(&options, dir.path()),
+            [
+                format!("{}/", dir.path().join("d").display()),
+                dir.path().join("f").display().to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn output_filters_valid_and_invalid() {
+        let dir = TempDir::new("output-filter");
+        dir.file("f");
+        let mut selection = SelectionState::new();
+        selection.add_paths([dir.path().join("f"), PathBuf::from("gone")]);
+        let mut options = OutputOptions::default();
+        assert_eq!(selection.to_output(&options, dir.path()), ["./f", "./gone"]);
+        options.filter = OutputFilter::ValidOnly;
+        assert_eq!(selection.to_output(&options, dir.path()), ["./f"]);
+        options.filter = OutputFilter::InvalidOnly;
+        assert_eq!(selection.to_output(&options, dir.path()), ["./gone"]);
+    }
+
+    #[test]
+    fn mtime_output_puts_invalid_paths_last() {
+        let dir = TempDir::new("output-mtime");
+        for (name, secs) in [("old", 100), ("new", 300), ("mid", 200)] {
+            let file = fs::File::options()
+                .write(true)
+                .open(dir.file(name))
+                .unwrap();
+            let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+            file.set_modified(time).unwrap();
+        }
+        let mut selection = select(&dir, &["mid", "new", "old"]);
+        selection.add_paths([PathBuf::from("gone")]);
+        let mut options = OutputOptions {
+            sort: OutputSort::Mtime,
+            ..Default::default()
+        };
+        let output = selection.to_output(&options, dir.path());
+        assert_eq!(output, ["./old", "./mid", "./new", "./gone"]);
+        options.reverse = true;
+        let output = selection.to_output(&options, dir.path());
+        assert_eq!(output, ["./new", "./mid", "./old", "./gone"]);
+    }
+
+    #[test]
+    fn emit_dirs_lists_distinct_parents() {
+        let dir = TempDir::new("emit-dirs");
+        for file in ["b/z", "a/x", "a/y"] {
+            dir.file(file);
+        }
+        let selection = select(&dir, &["b/z", "a/x", "a/y"]);
+        let options = OutputOptions {
+            emit_dirs: true,
+            ..Default::default()
+        };
+        assert_eq!(selection.to_output(&options, dir.path()), ["./a", "./b"]);
+    }
+
+    #[test]
+    fn shell_quote_escapes_quotes() {
+        assert_eq!(shell_quote("a b"), "'a b'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote("$HOME `x` \"y\" *"), "'$HOME `x` \"y\" *'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_quoted_output_survives_the_shell() {
+        let dir = TempDir::new("shell-quote");
+        let names = ["a b", "it's", "$x;`y`*", "new\nline"];
+        for name in names {
+            dir.file(name);
+        }
+        let selection = select(&dir, &names);
+        let options = OutputOptions {
+            shell_quote: true,
+            ..Default::default()
+        };
+        let output = selection.to_output(&options, dir.path());
+        let script = format!(
+            "for p in {}; do printf '%s\\0' \"$p\"; done",
+            output.join(" ")
+        );
+        let out = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .output()
+            .unwrap();
+        let mut expected: Vec<String> = names.iter().map(|name| format!("./{}\0", name)).collect();
+        expected.sort();
+        assert_eq!(String::from_utf8(out.stdout).unwrap(), expected.concat());
+    }
+
+    #[test]
+    fn dot_dot_paths_normalize() {
+        let cases = [
+            ("a/../b", "b"),
+            ("a/./b/..", "a"),
+            ("../sibling/file", "../sibling/file"),
+            ("a/../../x", "../x"),
+            ("/../x", "/x"),
+            ("a/..", "."),
+        ];
+        for (path, normalized) in cases {
+            assert_eq!(
+                normalize_path(Path::new(path)),
+                Path::new(normalized),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_paths_above_base_dir_display_absolute() {
+        let base = Path::new("/x/base");
+        let format = |path: &str| format_invalid_path(Path::new(path), base, false);
+        assert_eq!(format("a/../b"), format!("./{}", Path::new("b").display()));
+        assert_eq!(
+            format("../base/c/d"),
+            format!("./{}", Path::new("c/d").display())
+        );
+        assert_eq!(
+            format("../sibling/file"),
+            Path::new("/x/sibling/file").display().to_string()
+        );
+        assert_eq!(
+            resolve_invalid(Path::new("../../../up"), base),
+            Path::new("/up")
+        );
+    }
+
+    #[test]
+    fn dot_dot_pre_selections_land_on_the_real_file() {
+        let dir = TempDir::new("dot-dot-selection");
+        dir.file("a/x");
+        dir.file("b");
+        let selection = select(&dir, &["a/../b", "a/./x", "a/../a/x"]);
+        assert_eq!(selection.count(), 2);
+        let output = selection.to_output(&OutputOptions::default(), dir.path());
+        assert_eq!(output, ["./a/x", "./b"]);
+    }
+
+    #[test]
+    fn rel_common_strips_the_shared_directory() {
+        let dir = TempDir::new("rel-common");
+        dir.file("deep/sub/a");
+        dir.file("deep/sub/b/c");
+        let mut selection = select(&dir, &["deep/sub/a", "deep/sub/b/c"]);
+        let options = OutputOptions {
+            rel_common: true,
+            ..Default::default()
+        };
+        let b_c = format!("./{}", Path::new("b").join("c").display());
+        assert_eq!(
+            selection.to_output(&options, dir.path()),
+            ["./a".to_owned(), b_c]
+        );
+
+        selection.add_paths([dir.path().join("deep/gone")]);
+        let output = selection.to_output(&options, dir.path());
+        let sub = |rel: PathBuf| format!("./{}", Path::new("sub").join(rel).display());
+        let b_c = Path::new("b").join("c");
+        assert_eq!(output, ["./gone".to_owned(), sub("a".into()), sub(b_c)]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rel_common_without_a_shared_directory_is_absolute() {
+        let (a, b) = (PathBuf::from("/x/a"), PathBuf::from("/y/b"));
+        assert_eq!(common_ancestor(&[&a], &[&b], Path::new("/x")), None);
+        assert_eq!(
+            common_ancestor(&[&a], &[], Path::new("/")),
+            Some(PathBuf::from("/x"))
+        );
+    }
+
+    #[test]
+    fn both_emits_relative_tab_absolute() {
+        let dir = TempDir::new("both");
+        dir.file("d/f");
+        let mut selection = select(&dir, &["d"]);
+        selection.add_paths([PathBuf::from("gone")]);
+        let options = OutputOptions {
+            both: true,
+            dir_slash: true,
+            ..Default::default()
+        };
+        let absolute = |rel: &str| dir.path().join(rel).display().to_string();
+        assert_eq!(
+            selection.to_output(&options, dir.path()),
+            [
+                format!("./d/\t{}/", absolute("d")),
+                format!("./gone\t{}", absolute("gone"))
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keep_symlinks_emits_the_path_as_given() {
+        let dir = TempDir::new("keep-symlinks");
+        dir.file("real/f");
+        std::os::unix::fs::symlink(dir.path().join("real"), dir.path().join("link")).unwrap();
+        let selection = select(&dir, &["link/f"]);
+        let mut options = OutputOptions::default();
+        assert_eq!(selection.to_output(&options, dir.path()), ["./real/f"]);
+        options.keep_symlinks = true;
+        assert_eq!(selection.to_output(&options, dir.path()), ["./link/f"]);
+        assert!(selection.is_selected(&dir.path().join("real/f")));
+    }
+
+    #[test]
+    fn remove_below_keeps_selections_outside() {
+        let dir = TempDir::new("remove-below");
+        for file in ["in/a", "in/sub/b", "inside", "out/c"] {
+            dir.file(file);
+        }
+        let mut selection = select(&dir, &["in", "in/a", "in/sub/b", "inside", "out/c"]);
+        selection.add_paths([PathBuf::from("in/gone"), PathBuf::from("out/gone")]);
+        assert_eq!(
+            selection.remove_below(&dir.path().join("in"), dir.path()),
+            3
+        );
+        let output = selection.to_output(&OutputOptions::default(), dir.path());
+        assert_eq!(output, ["./in", "./inside", "./out/c", "./out/gone"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collapse_home_only_inside_home() {
+        let home = Path::new("/home/me");
+        let collapse = |path: &str| collapse_home(path.to_owned(), home);
+        assert_eq!(collapse("/home/me/notes.txt"), "~/notes.txt");
+        assert_eq!(collapse("/home/me/dir/"), "~/dir/");
+        assert_eq!(collapse("/home/me"), "~");
+        assert_eq!(collapse("/home/me/"), "~/");
+        assert_eq!(collapse("/home/meow/x"), "/home/meow/x");
+        assert_eq!(collapse("/etc/hosts"), "/etc/hosts");
+        assert_eq!(collapse("./home/me"), "./home/me");
+    }
+
+    #[test]
+    fn tilde_applies_to_absolute_output() {
+        let dir = TempDir::new("tilde");
+        dir.file("home/f");
+        dir.file("elsewhere");
+        let selection = select(&dir, &["home/f", "elsewhere"]);
+        let home = Some(dir.path().join("home"));
+        let mut options = OutputOptions {
+            home,
+            ..Default::default()
+        };
+        assert_eq!(
+            selection.to_output(&options, dir.path()),
+            ["./elsewhere", "./home/f"]
+        );
+        options.absolute = true;
+        let elsewhere = dir.path().join("elsewhere").display().to_string();
+        assert_eq!(
+            selection.to_output(&options, dir.path()),
+            [elsewhere, "~/f".to_owned()]
+        );
+    }
+
+    #[test]
+    fn git_relative_uses_the_nearest_repository() {
+        let dir = TempDir::new("git-relative");
+        dir.file("outer/.git/HEAD");
+        dir.file("outer/src/a");
+        // A submodule's .git is a file
+        dir.file("outer/inner/.git");
+        dir.file("outer/inner/b");
+        dir.file("plain/c");
+        let selection = select(
+            &dir,
+            &["outer/src/a", "outer/inner/b", "outer/inner", "plain/c"],
+        );
+        let options = OutputOptions {
+            git_relative: true,
+            ..Default::default()
+        };
+        let src_a = Path::new("src").join("a").display().to_string();
+        assert_eq!(
+            selection.to_output(&options, dir.path()),
+            [
+                ".".to_owned(),
+                "./plain/c".to_owned(),
+                "b".to_owned(),
+                src_a
+            ]
+        );
+    }
+
+    #[test]
+    fn add_paths_counts_duplicates_apart_from_limit() {
+        let dir = TempDir::new("add-counts");
+        let a = dir.file("a");
+        let b = dir.file("b");
+        let mut selection = SelectionState::new();
+        selection.set_limit(Some(2));
+        let counts = selection.add_paths([a.clone(), a, dir.path().join("missing"), b]);
+        let expected = AddCounts {
+            added: 2,
+            duplicates: 1,
+            refused: 1,
+        };
+        assert_eq!(counts, expected);
+        assert!(selection.take_limit_hit());
+    }
+
+    #[test]
+    fn remove_invalid_at_matches_any_stored_form() {
+        let base = Path::new("/base");
+        let mut selection = SelectionState::new();
+        selection.add_paths([PathBuf::from("/nowhere/sub/gone.txt")]);
+        let mut relative = SelectionState::new();
+        relative.add_paths([PathBuf::from("../nowhere/sub/gone.txt")]);
+
+        for selection in [&mut selection, &mut relative] {
+            let typed = Path::new("/nowhere/sub").join("../sub/gone.txt");
+            assert!(!selection.remove_invalid_at(Path::new("/nowhere/sub/other"), base));
+            assert!(selection.remove_invalid_at(&typed, base));
+            assert_eq!(selection.invalid_count(), 0);
         }
     }
+
+    /// `cargo test --release -- --ignored --nocapture add_paths_10k` compares
+    /// selecting 10k paths with canonicalizing each of them in full
+    #[test]
+    #[ignore]
+    fn add_paths_10k() {
+        let dir = TempDir::new("add-paths-10k");
+        let paths: Vec<PathBuf> = (0..10_000)
+            .map(|i| dir.file(&format!("a/b/c/d/e/f/{}/file{}", i % 100, i)))
+            .collect();
+
+        let start = std::time::Instant::now();
+        let canonical: HashSet<PathBuf> = paths.iter().map(|p| p.canonicalize().unwrap()).collect();
+        let full = start.elapsed();
+
+        let start = std::time::Instant::now();
+        let mut selection = SelectionState::new();
+        selection.add_paths(paths);
+        let cached = start.elapsed();
+
+        eprintln!("canonicalize: {:?}, add_paths: {:?}", full, cached);
+        let selected: HashSet<PathBuf> = selection.iter_valid().cloned().collect();
+        assert_eq!(selected, canonical);
+    }
 }
@@ -0,0 +1,35 @@
+//! Helpers shared by the unit tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A scratch directory removed again when the test ends
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// A fresh, canonical directory; `name` keeps parallel tests apart
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("file-select-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir.canonicalize().unwrap())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Create an empty file, and any directories leading to it
+    pub fn file(&self, rel: &str) -> PathBuf {
+        let path = self.0.join(rel);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "").unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,24 +1,26 @@
+use std::collections::HashMap;
 use std::path::Path;
+use std::time::{Duration, SystemTime};
 
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    widgets::{
+        Block, Borders, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState,
+    },
     Frame,
 };
 
-use crate::app::{App, FocusedPane};
+use crate::app::{display_relative, App, FocusedPane, InputMode, SelectedFilter};
+use crate::file_browser::Permissions;
+use crate::selection::resolve_invalid;
 
 /// Style constants
 mod styles {
     use super::*;
 
-    pub const CURSOR: &str = "> ";
-    pub const NO_CURSOR: &str = "  ";
-    pub const CHECKED: &str = "[x] ";
-    pub const UNCHECKED: &str = "[ ] ";
-
     pub fn focused_border() -> Style {
         Style::default().fg(Color::Cyan)
     }
@@ -50,27 +52,173 @@ mod styles {
     pub fn normal_style() -> Style {
         Style::default()
     }
+
+    pub fn group_header_style() -> Style {
+        Style::default()
+            .fg(Color::DarkGray)
+            .add_modifier(Modifier::BOLD)
+    }
+
+    pub fn selected_highlight() -> Style {
+        Style::default().bg(Color::DarkGray)
+    }
+
+    pub fn dim_style() -> Style {
+        Style::default().fg(Color::DarkGray)
+    }
+
+    /// Green below the first threshold, yellow below the second, else red
+    pub fn size_style(size: u64, (yellow, red): (u64, u64)) -> Style {
+        let color = if size >= red {
+            Color::Red
+        } else if size >= yellow {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        Style::default().fg(color)
+    }
+
+    pub fn extra_style() -> Style {
+        Style::default()
+            .fg(Color::Magenta)
+            .add_modifier(Modifier::ITALIC)
+    }
+
+    pub fn note_style() -> Style {
+        Style::default()
+            .fg(Color::Cyan)
+            .add_modifier(Modifier::ITALIC)
+    }
+}
+
+/// How selected entries are marked in the Files pane
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SelectionStyle {
+    /// `[x]` / `[ ]` checkbox column
+    #[default]
+    Checkbox,
+    /// Background highlight, no extra column
+    Highlight,
+    /// `*` marker column
+    Marker,
+}
+
+/// Which count of selections below it a directory label shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DirCount {
+    /// Everything selected anywhere below it, `dir/ (57)`
+    #[default]
+    Total,
+    /// Only its own selected children, `dir/ (3)`
+    Direct,
+    /// Both, direct first, `dir/ (3/57)`
+    Both,
 }
 
+/// Symbols for the cursor and selection state, each followed by a space.
+/// Each glyph and its counterpart are padded to the same display width, so
+/// names stay aligned whatever glyphs are configured.
+#[derive(Debug, Clone)]
+pub struct Glyphs {
+    pub cursor: String,
+    pub no_cursor: String,
+    pub checked: String,
+    pub unchecked: String,
+    pub marked: String,
+    pub unmarked: String,
+}
+
+impl Glyphs {
+    pub fn new(cursor: &str, checked: &str, unchecked: &str, marker: &str) -> Self {
+        let (cursor, no_cursor) = glyph_pair(cursor, "");
+        let (checked, unchecked) = glyph_pair(checked, unchecked);
+        let (marked, unmarked) = glyph_pair(marker, "");
+        Self {
+            cursor,
+            no_cursor,
+            checked,
+            unchecked,
+            marked,
+            unmarked,
+        }
+    }
+}
+
+/// Pad two glyphs to their common display width plus a separating space
+fn glyph_pair(on: &str, off: &str) -> (String, String) {
+    let width = Span::raw(on).width().max(Span::raw(off).width());
+    let pad = |glyph: &str| format!("{}{} ", glyph, " ".repeat(width - Span::raw(glyph).width()));
+    (pad(on), pad(off))
+}
+
+/// Seconds before an idle timeout at which the status bar starts counting down
+const COUNTDOWN_SECS: u64 = 5;
+
+/// Terminals narrower than this show one pane at a time
+const SINGLE_PANE_WIDTH: u16 = 60;
+
+/// Width of the right-aligned modification time column, e.g. `11mo ago`
+const AGE_WIDTH: usize = 8;
+
+/// Width of each of the owner and group fields of the permissions column
+const OWNER_WIDTH: usize = 8;
+
+/// Columns an icon is padded to, so wide replacement icons still align
+const ICON_WIDTH: usize = 2;
+
+/// Narrowest name worth keeping the detail columns for
+const MIN_NAME_WIDTH: usize = 4;
+
 pub fn render(frame: &mut Frame, app: &mut App) {
+    // Hidden bars get no rows, so the lists below size their scrolling to
+    // whatever height is left
+    let status_height = u16::from(app.status_shown() || countdown(app).is_some());
+    let legend_height = u16::from(app.show_legend);
     let [status_area, main_area, legend_area] = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(1),
+            Constraint::Length(status_height),
             Constraint::Min(0),
-            Constraint::Length(1),
+            Constraint::Length(legend_height),
         ])
         .areas(frame.area());
 
-    render_status_bar(frame, app, status_area);
+    if status_height > 0 {
+        render_status_bar(frame, app, status_area);
+    }
     render_main_panels(frame, app, main_area);
-    render_legend(frame, app, legend_area);
+    if legend_height > 0 {
+        render_legend(frame, app, legend_area);
+    }
+    if !app.color {
+        strip_colors(frame);
+    }
+}
+
+/// Drop every color from the drawn frame. Anything shown against a
+/// background (the status bar, legend keys, highlighted selections) is
+/// reversed instead, so it still stands out.
+fn strip_colors(frame: &mut Frame) {
+    for cell in &mut frame.buffer_mut().content {
+        if cell.bg != Color::Reset {
+            cell.modifier |= Modifier::REVERSED;
+        }
+        cell.fg = Color::Reset;
+        cell.bg = Color::Reset;
+    }
+}
+
+/// Time left before the idle timeout cancels, once it is close enough to warn
+fn countdown(app: &App) -> Option<Duration> {
+    app.timeout_remaining()
+        .filter(|left| left.as_secs() < COUNTDOWN_SECS)
 }
 
 fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
-    if app.search_mode {
-        let search_text = format!("/{}", app.search_query);
-        let status = Paragraph::new(search_text).style(
+    if let Some(ref paths) = app.pending_removal {
+        let prompt = format!(" Deselect {} files? (y/n)", paths.len());
+        let status = Paragraph::new(prompt).style(
             Style::default()
                 .bg(Color::DarkGray)
                 .fg(Color::Yellow)
@@ -80,21 +228,195 @@ fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
         return;
     }
 
-    let current_dir = app
-        .browser
-        .current_dir
-        .strip_prefix(&app.base_dir)
-        .map(|p| format!("./{}", p.display()))
-        .unwrap_or_else(|_| app.browser.current_dir.display().to_string());
+    let prompt = match app.input_mode {
+        InputMode::Normal => None,
+        InputMode::Search if app.search_failed => {
+            Some(format!("/{}  (no match)", app.search_query))
+        }
+        InputMode::Search => Some(format!("/{}", app.search_query)),
+        InputMode::Command => Some(format!(":{}", app.command_input)),
+        InputMode::Annotate => Some(format!("Note: {}", app.note_input)),
+        InputMode::Extension => Some(format!(
+            "Extension{}: {}",
+            if app.extension_ignore_case {
+                ""
+            } else {
+                " (match case)"
+            },
+            app.extension_input
+        )),
+    };
+
+    if let Some(prompt) = prompt {
+        let status = Paragraph::new(prompt).style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        );
+        frame.render_widget(status, area);
+        return;
+    }
 
-    let hidden_indicator = if app.browser.show_hidden { "[H]" } else { "[ ]" };
-    let status_text = format!(" {}  {}", current_dir, hidden_indicator);
+    let current_dir = app.tilde(if app.status_absolute {
+        app.browser.current_dir.display().to_string()
+    } else {
+        display_relative(&app.browser.current_dir, &app.base_dir)
+    });
 
-    let status = Paragraph::new(status_text).style(Style::default().bg(Color::DarkGray));
+    let hidden_indicator = if app.browser.hidden_only {
+        "[H only]"
+    } else if app.browser.show_hidden {
+        "[H]"
+    } else {
+        "[ ]"
+    };
+    let (dirs, files) = app.browser.entry_counts();
+    let mut tail = vec![
+        Span::raw(format!("  {}", hidden_indicator)),
+        Span::styled(
+            format!("  {} dirs, {} files", dirs, files),
+            Style::default().fg(Color::Gray),
+        ),
+    ];
+    if let Some(register) = app.macros.recording() {
+        tail.push(Span::styled(
+            format!("  recording @{}", register),
+            Style::default().fg(Color::Red),
+        ));
+    }
+    if app.only_selected {
+        tail.push(Span::styled(
+            "  [selected only]",
+            Style::default().fg(Color::Cyan),
+        ));
+    }
+    if app.diff {
+        tail.push(Span::styled(
+            format!("  [diff: {} missing]", app.selection.invalid_count()),
+            Style::default().fg(Color::Magenta),
+        ));
+    }
+    if single_pane(app, area.width) {
+        let pane = match app.focused_pane {
+            FocusedPane::Files => "  [Files]",
+            FocusedPane::Selected => "  [Selected]",
+        };
+        tail.push(Span::styled(pane, Style::default().fg(Color::Cyan)));
+    }
+    if let Some((done, total)) = app.add_progress() {
+        tail.push(Span::styled(
+            format!("  Adding… {}/{}", done, total),
+            Style::default().fg(Color::Yellow),
+        ));
+    } else if app.confirm_armed() {
+        tail.push(Span::styled(
+            format!(
+                "  Press Enter again to confirm {} files",
+                app.selection.count()
+            ),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ));
+    } else if let Some(ref message) = app.status_message {
+        tail.push(Span::styled(
+            format!("  {}", message),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if let Some(left) = countdown(app) {
+        tail.push(Span::styled(
+            format!("  Cancelling in {}s", left.as_secs() + 1),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    // Long paths lose their beginning so the current directory name stays visible
+    let tail_width: usize = tail.iter().map(Span::width).sum();
+    let path_width = (area.width as usize).saturating_sub(tail_width + 1);
+    let mut spans = vec![Span::raw(format!(
+        " {}",
+        truncate_left(&current_dir, path_width)
+    ))];
+    spans.extend(tail);
+
+    let status = Paragraph::new(Line::from(spans)).style(Style::default().bg(Color::DarkGray));
     frame.render_widget(status, area);
 }
 
+/// Shorten `text` to at most `max` characters by replacing its end with `…`.
+fn truncate_right(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_owned();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let head: String = text.chars().take(max - 1).collect();
+    format!("{}…", head)
+}
+
+/// Coarse time since `modified`, e.g. `5m ago` or `3d ago`
+fn format_age(modified: SystemTime) -> String {
+    let Ok(age) = SystemTime::now().duration_since(modified) else {
+        return "now".to_owned();
+    };
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{}s ago", secs),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        86400..2_592_000 => format!("{}d ago", secs / 86400),
+        2_592_000..31_536_000 => format!("{}mo ago", secs / 2_592_000),
+        _ => format!("{}y ago", secs / 31_536_000),
+    }
+}
+
+/// Octal mode and ownership in fixed widths, e.g. `0644 alice    staff   `
+fn format_permissions(permissions: &Permissions) -> String {
+    format!(
+        "{:04o} {:<width$} {:<width$}",
+        permissions.mode,
+        truncate_right(&permissions.owner, OWNER_WIDTH),
+        truncate_right(&permissions.group, OWNER_WIDTH),
+        width = OWNER_WIDTH
+    )
+}
+
+/// Shorten `text` to at most `max` characters by replacing its start with `…`.
+fn truncate_left(text: &str, max: usize) -> String {
+    let len = text.chars().count();
+    if len <= max {
+        return text.to_owned();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let tail: String = text.chars().skip(len - (max - 1)).collect();
+    format!("…{}", tail)
+}
+
+/// Whether only the focused pane is shown, filling the whole width
+fn single_pane(app: &App, width: u16) -> bool {
+    // A picker's one selection doesn't need a pane of its own
+    app.single_pane || app.single || width < SINGLE_PANE_WIDTH
+}
+
 fn render_main_panels(frame: &mut Frame, app: &mut App, area: Rect) {
+    if app.reviewing_invalid {
+        render_invalid_review(frame, app, area);
+        return;
+    }
+
+    if single_pane(app, area.width) {
+        match app.focused_pane {
+            FocusedPane::Files => render_file_list(frame, app, area),
+            FocusedPane::Selected => render_selection_list(frame, app, area),
+        }
+        return;
+    }
+
     let [files_area, selected_area] = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
@@ -105,17 +427,24 @@ fn render_main_panels(frame: &mut Frame, app: &mut App, area: Rect) {
 }
 
 fn render_file_list(frame: &mut Frame, app: &mut App, area: Rect) {
-    // Calculate visible height (area minus borders)
-    let visible_height = area.height.saturating_sub(2) as usize;
+    // Calculate visible height (area minus borders, and the last row when
+    // it is taken by the "more entries" sentinel)
+    let unloaded = app.browser.unloaded;
+    let visible_height = (area.height.saturating_sub(2) as usize)
+        .saturating_sub(usize::from(unloaded > 0))
+        .max(1);
     app.browser.adjust_scroll(visible_height);
 
-    let items: Vec<ListItem> = app
-        .browser
-        .entries
+    // Only build items for the visible window; large directories would
+    // otherwise allocate a ListItem per entry on every frame
+    let start = app.browser.scroll_offset.min(app.browser.entries.len());
+    let end = (start + visible_height).min(app.browser.entries.len());
+
+    let mut items: Vec<ListItem> = app.browser.entries[start..end]
         .iter()
         .enumerate()
         .map(|(i, entry)| {
-            let is_cursor = i == app.browser.cursor;
+            let is_cursor = start + i == app.browser.cursor;
             let is_selected = if entry.is_invalid {
                 app.selection.is_invalid_selected(&entry.path)
             } else {
@@ -123,18 +452,87 @@ fn render_file_list(frame: &mut Frame, app: &mut App, area: Rect) {
             };
 
             let name = format_entry_name(entry, app);
-            let cursor = if is_cursor { styles::CURSOR } else { styles::NO_CURSOR };
-            let checkbox = if is_selected { styles::CHECKED } else { styles::UNCHECKED };
+            let glyphs = &app.glyphs;
+            let cursor = if is_cursor {
+                &glyphs.cursor
+            } else {
+                &glyphs.no_cursor
+            };
 
-            let style = entry_style(entry.is_invalid, entry.is_dir, is_cursor);
+            let mut style = entry_style(entry.is_invalid, entry.is_dir, is_cursor);
+            // Invalid, directory and cursor styling take precedence
+            if let (Some(thresholds), Some(size), false) = (
+                app.size_thresholds,
+                entry.size,
+                is_cursor || entry.is_invalid,
+            ) {
+                style = styles::size_style(size, thresholds);
+            }
+            // In diff mode, files on disk missing from the list stand out
+            if app.diff && !is_selected && !is_cursor && !entry.is_invalid && !entry.is_dir {
+                style = styles::extra_style();
+            }
+            let indicator = match app.selection_style {
+                SelectionStyle::Checkbox if is_selected => Some(&glyphs.checked),
+                SelectionStyle::Checkbox => Some(&glyphs.unchecked),
+                SelectionStyle::Marker if is_selected => Some(&glyphs.marked),
+                SelectionStyle::Marker => Some(&glyphs.unmarked),
+                SelectionStyle::Highlight => {
+                    if is_selected {
+                        style = style.patch(styles::selected_highlight());
+                    }
+                    None
+                }
+            };
 
-            ListItem::new(Line::from(vec![
-                Span::styled(cursor, style),
-                Span::styled(checkbox, style),
-                Span::styled(name, style),
-            ]))
+            let mut spans = vec![Span::styled(cursor, style)];
+            spans.extend(indicator.map(|i| Span::styled(i, style)));
+
+            // Right-aligned details, the widest first so it is the first
+            // dropped when the name would be crowded out
+            let mut columns = Vec::new();
+            if app.show_permissions {
+                columns.extend(entry.permissions.as_ref().map(format_permissions));
+            }
+            if app.show_mtime {
+                columns.extend(
+                    entry
+                        .modified
+                        .map(|m| format!("{:>width$}", format_age(m), width = AGE_WIDTH)),
+                );
+            }
+            let prefix_width: usize = spans.iter().map(Span::width).sum();
+            let available = (area.width as usize).saturating_sub(2 + prefix_width);
+            let name_width = loop {
+                let columns_width: usize = columns.iter().map(|c| 1 + c.chars().count()).sum();
+                let name_width = available.saturating_sub(columns_width);
+                if columns.is_empty() || name_width >= MIN_NAME_WIDTH {
+                    break name_width;
+                }
+                columns.remove(0);
+            };
+            if columns.is_empty() {
+                spans.push(Span::styled(name, style));
+            } else {
+                let name = truncate_right(&name, name_width);
+                let padding = name_width - name.chars().count();
+                spans.push(Span::styled(name, style));
+                spans.push(Span::raw(" ".repeat(padding)));
+                for column in columns {
+                    spans.push(Span::styled(format!(" {}", column), styles::dim_style()));
+                }
+            }
+            ListItem::new(Line::from(spans))
         })
         .collect();
+    if unloaded > 0 {
+        // Only the loaded entries are sorted, so say the order is partial
+        items.extend((items.len()..visible_height).map(|_| ListItem::new("")));
+        items.push(ListItem::new(Span::styled(
+            format!("\u{2026} ({} more unsorted, L loads)", unloaded),
+            styles::dim_style(),
+        )));
+    }
 
     let is_focused = app.focused_pane == FocusedPane::Files;
     let border_style = if is_focused {
@@ -150,25 +548,84 @@ fn render_file_list(frame: &mut Frame, app: &mut App, area: Rect) {
             .border_style(border_style),
     );
 
-    let mut state = ListState::default()
-        .with_selected(Some(app.browser.cursor))
-        .with_offset(app.browser.scroll_offset);
+    let mut state = ListState::default().with_selected(app.browser.cursor.checked_sub(start));
     frame.render_stateful_widget(list, area, &mut state);
+
+    if app.scrollbar {
+        let total = app.browser.entries.len();
+        render_scrollbar(
+            frame,
+            area,
+            total,
+            visible_height,
+            app.browser.scroll_offset,
+        );
+    }
 }
 
 fn format_entry_name(entry: &crate::file_browser::FileEntry, app: &App) -> String {
+    let name = entry_label(entry, app);
+    if !app.icons {
+        return name;
+    }
+    let icon = entry_icon(entry, &app.icon_overrides);
+    let padding = ICON_WIDTH.saturating_sub(Span::raw(icon).width());
+    format!("{}{} {}", icon, " ".repeat(padding), name)
+}
+
+fn entry_label(entry: &crate::file_browser::FileEntry, app: &App) -> String {
     if entry.is_dir {
-        let count = count_selected_in_dir(&entry.path, app);
-        if count > 0 {
-            format!("{}/ ({})", entry.name, count)
-        } else {
-            format!("{}/", entry.name)
+        let (direct, total) = count_selected_in_dir(&entry.path, app);
+        match app.dir_count {
+            _ if total == 0 => format!("{}/", entry.name),
+            DirCount::Total => format!("{}/ ({})", entry.name, total),
+            DirCount::Direct if direct == 0 => format!("{}/", entry.name),
+            DirCount::Direct => format!("{}/ ({})", entry.name, direct),
+            DirCount::Both => format!("{}/ ({}/{})", entry.name, direct, total),
         }
     } else {
         entry.name.clone()
     }
 }
 
+/// Nerd Font icon for an entry: by kind for invalid entries, symlinks and
+/// directories, otherwise by extension
+fn entry_icon<'a>(
+    entry: &crate::file_browser::FileEntry,
+    overrides: &'a HashMap<String, String>,
+) -> &'a str {
+    if entry.is_invalid {
+        return "\u{f05e}";
+    }
+    if entry.path.is_symlink() {
+        return "\u{f0c1}";
+    }
+    if entry.is_dir {
+        return "\u{f07b}";
+    }
+
+    let ext = entry
+        .path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if let Some(icon) = overrides.get(&ext) {
+        return icon;
+    }
+    match ext.as_str() {
+        "rs" | "c" | "h" | "cc" | "cpp" | "hpp" | "go" | "py" | "rb" | "js" | "ts" | "jsx"
+        | "tsx" | "java" | "kt" | "swift" | "sh" | "bash" | "zsh" | "lua" | "html" | "css" => {
+            "\u{f121}"
+        }
+        "png" | "jpg" | "jpeg" | "gif" | "bmp" | "svg" | "webp" | "ico" | "tif" | "tiff" => {
+            "\u{f03e}"
+        }
+        "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" => "\u{f410}",
+        "md" | "txt" | "rst" | "pdf" | "doc" | "docx" => "\u{f15c}",
+        _ => "\u{f15b}",
+    }
+}
+
 fn entry_style(is_invalid: bool, is_dir: bool, is_cursor: bool) -> Style {
     match (is_invalid, is_cursor) {
         (true, true) => styles::invalid_cursor_style(),
@@ -179,58 +636,118 @@ fn entry_style(is_invalid: bool, is_dir: bool, is_cursor: bool) -> Style {
     }
 }
 
-fn count_selected_in_dir(dir_path: &Path, app: &App) -> usize {
+/// Selections directly inside a directory and anywhere below it
+fn count_selected_in_dir(dir_path: &Path, app: &App) -> (usize, usize) {
     let Ok(dir_canonical) = dir_path.canonicalize() else {
-        return 0;
+        return (0, 0);
     };
 
-    let valid_count = app
-        .selection
-        .iter_valid()
-        .filter(|p| p.starts_with(&dir_canonical))
-        .count();
-
-    let invalid_count = app
-        .selection
+    let (mut direct, mut total) = (0, 0);
+    let mut count = |path: &Path| {
+        if path.starts_with(&dir_canonical) && path != dir_canonical {
+            total += 1;
+            if path.parent() == Some(&dir_canonical) {
+                direct += 1;
+            }
+        }
+    };
+    // A directly-selected directory is shown by its checkbox, not its count
+    app.selection.iter_valid().for_each(|p| count(p));
+    app.selection
         .iter_invalid()
-        .filter(|p| {
-            let full_path = if p.is_absolute() {
-                p.to_path_buf()
-            } else {
-                app.base_dir.join(p)
-            };
-            full_path.starts_with(&dir_canonical)
-        })
-        .count();
+        .for_each(|p| count(&resolve_invalid(p, &app.base_dir)));
 
-    valid_count + invalid_count
+    (direct, total)
 }
 
-fn render_selection_list(frame: &mut Frame, app: &mut App, area: Rect) {
-    // Calculate visible height and adjust scroll
-    let visible_height = area.height.saturating_sub(2) as usize;
-    app.adjust_selected_scroll(visible_height);
+/// A row in the Selected pane: either a selected path or, when grouping by
+/// directory, a non-selectable header
+enum SelectedRow {
+    Header(String),
+    Item {
+        index: usize,
+        text: String,
+        is_valid: bool,
+        note: Option<String>,
+    },
+}
+
+/// A selected path as shown in the Selected pane
+struct DisplayPath {
+    text: String,
+    is_valid: bool,
+    note: Option<String>,
+}
 
-    let title = format!("Selected ({})", app.selection.count());
+fn render_selection_list(frame: &mut Frame, app: &mut App, area: Rect) {
+    let title = match app.selected_filter {
+        SelectedFilter::All => format!("Selected ({})", app.selection.count()),
+        SelectedFilter::Valid => format!("Selected ({} valid shown)", app.shown_selected_count()),
+        SelectedFilter::Invalid => {
+            format!("Selected ({} invalid shown)", app.shown_selected_count())
+        }
+    };
     let is_focused = app.focused_pane == FocusedPane::Selected;
 
-    let all_paths = collect_display_paths(app);
+    let mut paths = collect_display_paths(app);
+    let prefix = if app.collapse_prefix {
+        strip_common_dir(&mut paths)
+    } else {
+        None
+    };
+    let mut rows = build_selected_rows(paths, app.group_selected);
+    if let Some(prefix) = prefix {
+        rows.insert(0, SelectedRow::Header(prefix));
+    }
+    let cursor_row = rows
+        .iter()
+        .position(
+            |row| matches!(row, SelectedRow::Item { index, .. } if *index == app.selected_cursor),
+        )
+        .unwrap_or(0);
+    // Keep a group's header in view when its first item has the cursor
+    let top_row = match cursor_row.checked_sub(1).map(|i| &rows[i]) {
+        Some(SelectedRow::Header(_)) => cursor_row - 1,
+        _ => cursor_row,
+    };
+
+    // Calculate visible height and adjust scroll
+    let visible_height = area.height.saturating_sub(2) as usize;
+    app.adjust_selected_scroll(top_row, cursor_row, visible_height);
 
-    let items: Vec<ListItem> = all_paths
+    let total_rows = rows.len();
+    let items: Vec<ListItem> = rows
         .into_iter()
-        .enumerate()
-        .map(|(i, (display, is_valid))| {
-            let is_cursor = is_focused && i == app.selected_cursor;
-            let cursor = if is_cursor { styles::CURSOR } else { styles::NO_CURSOR };
-
-            let style = match (is_valid, is_cursor) {
-                (_, true) if !is_valid => styles::invalid_cursor_style(),
-                (_, true) => styles::cursor_style(),
-                (false, false) => styles::invalid_style(),
-                (true, false) => styles::normal_style(),
-            };
+        .map(|row| match row {
+            SelectedRow::Header(dir) => {
+                ListItem::new(Line::from(Span::styled(dir, styles::group_header_style())))
+            }
+            SelectedRow::Item {
+                index,
+                text,
+                is_valid,
+                note,
+            } => {
+                let is_cursor = is_focused && index == app.selected_cursor;
+                let cursor = if is_cursor {
+                    &app.glyphs.cursor
+                } else {
+                    &app.glyphs.no_cursor
+                };
 
-            ListItem::new(Line::from(Span::styled(format!("{}{}", cursor, display), style)))
+                let style = match (is_valid, is_cursor) {
+                    (_, true) if !is_valid => styles::invalid_cursor_style(),
+                    (_, true) => styles::cursor_style(),
+                    (false, false) => styles::invalid_style(),
+                    (true, false) => styles::normal_style(),
+                };
+
+                let mut spans = vec![Span::styled(format!("{}{}", cursor, text), style)];
+                if let Some(note) = note {
+                    spans.push(Span::styled(format!("  # {}", note), styles::note_style()));
+                }
+                ListItem::new(Line::from(spans))
+            }
         })
         .collect();
 
@@ -247,26 +764,165 @@ fn render_selection_list(frame: &mut Frame, app: &mut App, area: Rect) {
             .border_style(border_style),
     );
 
-    let selected = if is_focused { Some(app.selected_cursor) } else { None };
+    let selected = if is_focused { Some(cursor_row) } else { None };
     let mut state = ListState::default()
         .with_selected(selected)
         .with_offset(app.selected_scroll_offset);
     frame.render_stateful_widget(list, area, &mut state);
+
+    if app.scrollbar {
+        render_scrollbar(
+            frame,
+            area,
+            total_rows,
+            visible_height,
+            app.selected_scroll_offset,
+        );
+    }
+}
+
+/// Draw a scrollbar over the right border of a list block when its
+/// `total` rows don't fit in `visible` rows
+fn render_scrollbar(frame: &mut Frame, area: Rect, total: usize, visible: usize, offset: usize) {
+    if total <= visible {
+        return;
+    }
+    let mut state = ScrollbarState::new(total - visible)
+        .viewport_content_length(visible)
+        .position(offset);
+    let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+        .begin_symbol(None)
+        .end_symbol(None);
+    let track = area.inner(Margin {
+        vertical: 1,
+        horizontal: 0,
+    });
+    frame.render_stateful_widget(scrollbar, track, &mut state);
+}
+
+/// Invalid selections next to the absolute path each one resolves to
+fn render_invalid_review(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .get_invalid_list()
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let is_cursor = i == app.invalid_cursor;
+            let cursor = if is_cursor {
+                &app.glyphs.cursor
+            } else {
+                &app.glyphs.no_cursor
+            };
+            let style = if is_cursor {
+                styles::invalid_cursor_style()
+            } else {
+                styles::invalid_style()
+            };
+            let display = app.format_path_for_display(path, false);
+            let resolved = resolve_invalid(path, &app.base_dir);
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{}{}", cursor, display), style),
+                Span::styled(format!("  -> {}", resolved.display()), styles::dim_style()),
+            ]))
+        })
+        .collect();
+
+    let title = format!(
+        "Invalid selections ({}) - Space deselect, i close",
+        app.selection.invalid_count()
+    );
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .border_style(styles::focused_border()),
+    );
+    let mut state = ListState::default().with_selected(Some(app.invalid_cursor));
+    frame.render_stateful_widget(list, area, &mut state);
 }
 
-fn collect_display_paths(app: &App) -> Vec<(String, bool)> {
-    let mut paths: Vec<(String, bool)> = app
+/// Lay out sorted display paths as rows, inserting a header before each run
+/// of paths sharing a parent directory when `grouped` is set.
+fn build_selected_rows(paths: Vec<DisplayPath>, grouped: bool) -> Vec<SelectedRow> {
+    let mut rows = Vec::with_capacity(paths.len());
+    let mut current_dir: Option<String> = None;
+
+    for (
+        index,
+        DisplayPath {
+            text,
+            is_valid,
+            note,
+        },
+    ) in paths.into_iter().enumerate()
+    {
+        if !grouped {
+            rows.push(SelectedRow::Item {
+                index,
+                text,
+                is_valid,
+                note,
+            });
+            continue;
+        }
+
+        let (dir, name) = match text.rsplit_once('/') {
+            Some((dir, name)) => (format!("{}/", dir), name.to_owned()),
+            None => (String::new(), text.clone()),
+        };
+        if current_dir.as_ref() != Some(&dir) {
+            rows.push(SelectedRow::Header(dir.clone()));
+            current_dir = Some(dir);
+        }
+        rows.push(SelectedRow::Item {
+            index,
+            text: format!("  {}", name),
+            is_valid,
+            note,
+        });
+    }
+
+    rows
+}
+
+/// Remove the deepest directory shared by every path from their display
+/// text and return it. Paths with nothing in common beyond `./` or `/`
+/// are left alone.
+fn strip_common_dir(paths: &mut [DisplayPath]) -> Option<String> {
+    let first = &paths.first()?.text;
+    let mut prefix = &first[..first.rfind('/')? + 1];
+    for path in paths.iter() {
+        while !path.text.starts_with(prefix) {
+            // Drop the last component, keeping the trailing slash
+            prefix = &prefix[..prefix[..prefix.len() - 1].rfind('/')? + 1];
+        }
+    }
+    if prefix == "./" || prefix == "/" {
+        return None;
+    }
+
+    let prefix = prefix.to_owned();
+    for path in paths.iter_mut() {
+        path.text.drain(..prefix.len());
+    }
+    Some(prefix)
+}
+
+fn collect_display_paths(app: &App) -> Vec<DisplayPath> {
+    let display = |path: &Path, is_valid| DisplayPath {
+        text: app.format_path_for_display(path, is_valid),
+        is_valid,
+        note: app.selection.note(path).map(str::to_owned),
+    };
+    let mut paths: Vec<DisplayPath> = app
         .selection
         .iter_valid()
-        .map(|p| (app.format_path_for_display(p, true), true))
-        .chain(
-            app.selection
-                .iter_invalid()
-                .map(|p| (app.format_path_for_display(p, false), false)),
-        )
+        .map(|p| display(p, true))
+        .chain(app.selection.iter_invalid().map(|p| display(p, false)))
+        .filter(|p| app.selected_filter.shows(p.is_valid))
         .collect();
 
-    paths.sort_by(|a, b| a.0.cmp(&b.0));
+    paths.sort_by(|a, b| a.text.cmp(&b.text));
     paths
 }
 
@@ -278,23 +934,8 @@ fn render_legend(frame: &mut Frame, app: &App, area: Rect) {
     let desc_style = Style::default().fg(Color::Gray);
     let sep_style = Style::default().fg(Color::DarkGray);
 
-    let mut bindings = vec![
-        ("Tab", "pane"),
-        ("Space", "sel"),
-        ("a", "all"),
-        ("r", "rec"),
-        ("/", "search"),
-    ];
-
-    if app.can_save() {
-        bindings.push(("s", "save"));
-    }
-
-    bindings.push(("Enter", "ok"));
-    bindings.push(("q", "quit"));
-
     let mut spans = Vec::new();
-    for (i, (key, desc)) in bindings.iter().enumerate() {
+    for (i, (key, desc)) in legend_bindings(app).iter().enumerate() {
         if i > 0 {
             spans.push(Span::styled("│", sep_style));
         }
@@ -304,3 +945,135 @@ fn render_legend(frame: &mut Frame, app: &App, area: Rect) {
 
     frame.render_widget(Paragraph::new(Line::from(spans)), area);
 }
+
+/// The legend's keys and descriptions: only keys that do something in the
+/// focused pane, with live state where a key toggles it
+fn legend_bindings(app: &App) -> Vec<(&'static str, String)> {
+    let mut bindings: Vec<(&str, String)> = vec![("Tab", "pane".into())];
+    match app.focused_pane {
+        FocusedPane::Files => {
+            if !app.read_only {
+                bindings.push(("Space", "sel".into()));
+                bindings.push(("a", "all".into()));
+                bindings.push(("r", "rec".into()));
+            }
+            bindings.push(("/", "search".into()));
+            let hidden = if app.browser.hidden_only {
+                "only"
+            } else if app.browser.show_hidden {
+                "on"
+            } else {
+                "off"
+            };
+            bindings.push((".", format!("hidden: {}", hidden)));
+        }
+        FocusedPane::Selected => {
+            if !app.read_only {
+                bindings.push(("Space", "desel".into()));
+            }
+            let filter = match app.selected_filter {
+                SelectedFilter::All => "all",
+                SelectedFilter::Valid => "valid",
+                SelectedFilter::Invalid => "invalid",
+            };
+            bindings.push(("v", format!("showing {}", filter)));
+        }
+    }
+
+    if app.can_save() && !app.read_only {
+        bindings.push(("s", "save".into()));
+    }
+
+    let output = if app.absolute_output() { "abs" } else { "rel" };
+    bindings.push(("Enter", format!("ok ({})", output)));
+    bindings.push(("q", "quit".into()));
+    bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_util::TempDir;
+    use clap::Parser;
+
+    #[test]
+    fn size_bands_around_the_thresholds() {
+        let thresholds = (1 << 20, 100 << 20);
+        let band = |size| styles::size_style(size, thresholds).fg;
+        assert_eq!(band(0), Some(Color::Green));
+        assert_eq!(band((1 << 20) - 1), Some(Color::Green));
+        assert_eq!(band(1 << 20), Some(Color::Yellow));
+        assert_eq!(band((100 << 20) - 1), Some(Color::Yellow));
+        assert_eq!(band(100 << 20), Some(Color::Red));
+    }
+
+    fn legend(app: &App) -> Vec<String> {
+        let bindings = legend_bindings(app);
+        bindings
+            .iter()
+            .map(|(key, desc)| format!("{} {}", key, desc))
+            .collect()
+    }
+
+    #[test]
+    fn legend_per_pane_and_mode() {
+        let dir = TempDir::new("legend");
+        let app_with = |args: &[&str]| {
+            let args = std::iter::once("file-select").chain(args.iter().copied());
+            App::new(dir.path().to_owned(), Vec::new(), &Config::parse_from(args)).unwrap()
+        };
+
+        let mut app = app_with(&[]);
+        assert_eq!(
+            legend(&app),
+            [
+                "Tab pane",
+                "Space sel",
+                "a all",
+                "r rec",
+                "/ search",
+                ". hidden: off",
+                "Enter ok (rel)",
+                "q quit"
+            ]
+        );
+        app.focused_pane = FocusedPane::Selected;
+        app.selected_filter = SelectedFilter::Invalid;
+        assert_eq!(
+            legend(&app),
+            [
+                "Tab pane",
+                "Space desel",
+                "v showing invalid",
+                "Enter ok (rel)",
+                "q quit"
+            ]
+        );
+
+        let mut app = app_with(&["--read-only", "--absolute", "--hidden", "--file", "out"]);
+        assert_eq!(
+            legend(&app),
+            [
+                "Tab pane",
+                "/ search",
+                ". hidden: on",
+                "Enter ok (abs)",
+                "q quit"
+            ]
+        );
+        app.read_only = false;
+        app.focused_pane = FocusedPane::Selected;
+        assert_eq!(
+            legend(&app),
+            [
+                "Tab pane",
+                "Space desel",
+                "v showing all",
+                "s save",
+                "Enter ok (abs)",
+                "q quit"
+            ]
+        );
+    }
+}